@@ -0,0 +1,44 @@
+//! Sorting tracked time into categories by the program and title of the window it was spent in.
+
+use chrono::Duration;
+use pattern::Pattern;
+use probe::Segment;
+
+pub struct Rule {
+    pub category: String,
+    pub pattern: Pattern,
+}
+
+impl Rule {
+    /// A rule putting windows of `program` whose title matches the regex `title` in `category`.
+    /// Returns None if the regex is invalid.
+    pub fn new(category: &str, program: Option<&str>, title: Option<&str>) -> Option<Rule> {
+        Some(Rule {
+            category: category.into(),
+            pattern: Pattern::new(program, title)?,
+        })
+    }
+}
+
+/// The category of the first rule that matches the segment.
+pub fn categorise<'a>(rules: &'a [Rule], segment: &Segment) -> Option<&'a str> {
+    rules.iter().find(|rule| rule.pattern.matches(segment)).map(|rule| &rule.category[..])
+}
+
+/// The seconds spent in each category, most first. Time that no rule matches is left out.
+pub fn totals(rules: &[Rule], segments: &[Segment]) -> Vec<(String, i64)> {
+    let mut times: Vec<(&str, Duration)> = vec![];
+    for segment in segments {
+        if let Some(category) = categorise(rules, segment) {
+            let time = segment.end - segment.start;
+            match times.iter().position(|&(c, _)| c == category) {
+                Some(i) => times[i].1 = times[i].1 + time,
+                None => times.push((category, time)),
+            }
+        }
+    }
+    let mut totals: Vec<(String, i64)> =
+        times.into_iter().map(|(category, time)| (category.into(), time.num_seconds())).collect();
+    totals.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    totals
+}
@@ -1,9 +1,12 @@
 use regex::Regex;
 use chrono::*;
 
+/// Parses a date and/or time like "yesterday 14:00", "last monday" or "3 hrs ago" relative to
+/// `now`. Returns None if nothing in `s` can be read as either.
 pub fn parse_datetime<Tz: TimeZone>(s: &str, now: DateTime<Tz>) -> Option<DateTime<Tz>> {
     lazy_static! {
         static ref DATE_WORDS_RE: Regex = Regex::new(r"today|yesterday|now").unwrap();
+        static ref DATE_WEEKDAY_RE: Regex = Regex::new(r"(?i)last (?P<day>[a-z]+)").unwrap();
         static ref DATE_ABSOLUTE_RE: Regex = Regex::new(r"(?P<day>\d{1,2})/(?P<month>\d{1,2})(/(?P<year>\d{4}|\d{2}))?").unwrap();
         static ref DATE_RELATIVE_RE: Regex = Regex::new(r"(?P<n>\d+) (days?|ds?)(?P<ago>( ago)?)").unwrap();
         static ref TIME_ABSOLUTE_RE: Regex = Regex::new(r"(?P<hr>\d{2}):(?P<mins>\d{2})").unwrap();
@@ -11,12 +14,21 @@ pub fn parse_datetime<Tz: TimeZone>(s: &str, now: DateTime<Tz>) -> Option<DateTi
             (?P<n>\d+) \s (?P<dur>hrs?|hours?|hs?|minutes?|mins?|ms?) (?P<ago>(\s ago)?)").unwrap();
     }
 
+    let mut matched = true;
     let now = if let Some(caps) = DATE_WORDS_RE.captures(s) {
         match caps.at(0) {
             Some("now") | Some("today") | None => now,
             Some("yesterday") => now - Duration::days(1),
             _ => unreachable!(),
         }
+    } else if let Some(caps) = DATE_WEEKDAY_RE.captures(s) {
+        // the most recent one before today, so "last tuesday" on a Tuesday is a week ago
+        let day = parse_weekday(&caps.name("day").unwrap().to_lowercase())?;
+        let mut date = now.date().pred();
+        while date.weekday() != day {
+            date = date.pred();
+        }
+        now.clone() - (now.date() - date)
     } else if let Some(caps) = DATE_ABSOLUTE_RE.captures(s) {
         let day = caps.name("day").unwrap().parse().unwrap();
         let month = caps.name("month").unwrap().parse().unwrap();
@@ -36,6 +48,7 @@ pub fn parse_datetime<Tz: TimeZone>(s: &str, now: DateTime<Tz>) -> Option<DateTi
             _ => now + duration,
         }
     } else {
+        matched = false;
         now
     };
 
@@ -56,8 +69,243 @@ pub fn parse_datetime<Tz: TimeZone>(s: &str, now: DateTime<Tz>) -> Option<DateTi
             Some(s) if s.ends_with("ago") => now - duration,
             _ => now + duration
         }
+    } else if !matched {
+        return None;
     } else {
         now
     };
     Some(now)
 }
+
+/// Parses the end of a period that starts at `start`. "+90m" is that long after the start and a
+/// bare time like "13:00" is on the day of the start, while anything else, eg. "yesterday 13:00",
+/// is read relative to `now` like any other date. The end can still come before the start.
+pub fn parse_end<Tz: TimeZone>(s: &str, start: DateTime<Tz>, now: DateTime<Tz>) -> Option<DateTime<Tz>> {
+    lazy_static! {
+        static ref BARE_TIME_RE: Regex = Regex::new(r"^\s*\d{2}:\d{2}\s*$").unwrap();
+    }
+    if s.starts_with('+') {
+        parse_duration(&s[1..]).map(|duration| start + duration)
+    } else if BARE_TIME_RE.is_match(s) {
+        parse_datetime(s, start)
+    } else {
+        parse_datetime(s, now)
+    }
+}
+
+pub fn parse_duration(s: &str) -> Option<Duration> {
+    lazy_static! {
+        static ref DURATION_RE: Regex = Regex::new(r"(?x)
+            (?P<n>\d+(\.\d+)?) \s* (?P<unit>hours?|hrs?|h|minutes?|mins?|m|seconds?|secs?|s)").unwrap();
+    }
+
+    // Everything in the string has to be part of a duration, otherwise "1h and a bit" would be
+    // silently read as an hour
+    if !DURATION_RE.is_match(s) || !DURATION_RE.replace_all(s, "").trim().is_empty() {
+        return None;
+    }
+    let mut seconds = 0.0;
+    for caps in DURATION_RE.captures_iter(s) {
+        let n: f64 = caps.name("n").unwrap().parse().unwrap();
+        let unit = caps.name("unit").unwrap();
+        seconds += if unit.starts_with("h") {
+            n * 3600.0
+        } else if unit.starts_with("m") {
+            n * 60.0
+        } else {
+            n
+        };
+    }
+    Some(Duration::seconds(seconds.round() as i64))
+}
+
+/// When a recurring entry happens, eg. "weekdays 09:30 for 15m" or "mon,thu 14:00 for 1h".
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schedule {
+    pub days: Vec<Weekday>,
+    pub hour: u32,
+    pub minute: u32,
+    pub duration: Duration,
+}
+
+impl Schedule {
+    /// The start of each occurrence between `since` and `until`
+    pub fn occurrences<Tz: TimeZone>(&self, since: DateTime<Tz>, until: DateTime<Tz>) -> Vec<DateTime<Tz>> {
+        let mut occurrences = vec![];
+        let mut day = since.date();
+        while day <= until.date() {
+            if self.days.contains(&day.weekday()) {
+                if let Some(start) = day.and_hms_opt(self.hour, self.minute, 0) {
+                    if start >= since && start <= until {
+                        occurrences.push(start);
+                    }
+                }
+            }
+            day = day.succ();
+        }
+        occurrences
+    }
+}
+
+fn parse_weekday(s: &str) -> Option<Weekday> {
+    match s {
+        "mon" | "monday" => Some(Weekday::Mon),
+        "tue" | "tues" | "tuesday" => Some(Weekday::Tue),
+        "wed" | "wednesday" => Some(Weekday::Wed),
+        "thu" | "thurs" | "thursday" => Some(Weekday::Thu),
+        "fri" | "friday" => Some(Weekday::Fri),
+        "sat" | "saturday" => Some(Weekday::Sat),
+        "sun" | "sunday" => Some(Weekday::Sun),
+        _ => None,
+    }
+}
+
+/// Parses a set of days like "weekdays", "mon,thu" or "mon-fri".
+fn parse_days(s: &str) -> Option<Vec<Weekday>> {
+    match s {
+        "daily" | "every day" => {
+            return Some(vec![Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri,
+                             Weekday::Sat, Weekday::Sun])
+        }
+        "weekdays" => return Some(vec![Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri]),
+        "weekends" => return Some(vec![Weekday::Sat, Weekday::Sun]),
+        _ => {}
+    }
+    let mut weekdays = vec![];
+    for days in s.split(|c: char| c == ',' || c.is_whitespace()).filter(|d| !d.is_empty()) {
+        let mut range = days.splitn(2, |c| c == '-' || c == '–');
+        let first = match parse_weekday(range.next().unwrap()) {
+            Some(day) => day,
+            None => return None,
+        };
+        let last = match range.next().map(parse_weekday) {
+            Some(Some(day)) => day,
+            Some(None) => return None,
+            None => first,
+        };
+        let mut day = first;
+        weekdays.push(day);
+        while day != last {
+            day = day.succ();
+            weekdays.push(day);
+        }
+    }
+    Some(weekdays)
+}
+
+/// When someone is meant to be working, eg. "mon-fri 09:00-17:30".
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorkingHours {
+    pub days: Vec<Weekday>,
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+/// The stretches of working time between `since` and `until`, in order.
+pub fn working_intervals<Tz: TimeZone>(hours: &[WorkingHours],
+                                       since: DateTime<Tz>,
+                                       until: DateTime<Tz>)
+                                       -> Vec<(DateTime<Tz>, DateTime<Tz>)> {
+    let mut intervals = vec![];
+    let mut day = since.date();
+    while day <= until.date() {
+        let mut today = vec![];
+        for hours in hours.iter().filter(|hours| hours.days.contains(&day.weekday())) {
+            let start = day.and_time(hours.start);
+            let end = day.and_time(hours.end);
+            if let (Some(start), Some(end)) = (start, end) {
+                let start = if start < since { since.clone() } else { start };
+                let end = if end > until { until.clone() } else { end };
+                if start < end {
+                    today.push((start, end));
+                }
+            }
+        }
+        today.sort_by(|a, b| a.0.cmp(&b.0));
+        intervals.append(&mut today);
+        day = day.succ();
+    }
+    intervals
+}
+
+/// How much working time there is between `since` and `until`, leaving out `days_off`.
+pub fn working_time<Tz: TimeZone>(hours: &[WorkingHours],
+                                  since: DateTime<Tz>,
+                                  until: DateTime<Tz>,
+                                  days_off: &[NaiveDate])
+                                  -> Duration {
+    working_intervals(hours, since, until)
+        .into_iter()
+        .filter(|&(ref start, _)| !days_off.contains(&start.naive_local().date()))
+        .fold(Duration::zero(), |total, (start, end)| total + (end - start))
+}
+
+/// Parses working hours like "mon-fri 09:00-17:30". Days with different hours are separated by
+/// semicolons, eg. "mon-thu 09:00-17:30; fri 09:00-13:00".
+pub fn parse_working_hours(s: &str) -> Option<Vec<WorkingHours>> {
+    lazy_static! {
+        static ref HOURS_RE: Regex = Regex::new(r"(?x)
+            ^ (?P<days>.+?) \s+ (?P<start>\d{1,2}:\d{2}) \s* [-–] \s* (?P<end>\d{1,2}:\d{2}) $").unwrap();
+    }
+
+    let s = s.trim().to_lowercase();
+    let mut working_hours = vec![];
+    for part in s.split(';').map(|part| part.trim()).filter(|part| !part.is_empty()) {
+        let caps = match HOURS_RE.captures(part) {
+            Some(caps) => caps,
+            None => return None,
+        };
+        let days = match parse_days(caps.name("days").unwrap()) {
+            Some(days) => days,
+            None => return None,
+        };
+        let start = NaiveTime::parse_from_str(caps.name("start").unwrap(), "%H:%M");
+        let end = NaiveTime::parse_from_str(caps.name("end").unwrap(), "%H:%M");
+        match (start, end) {
+            (Ok(start), Ok(end)) if start < end => {
+                working_hours.push(WorkingHours {
+                    days: days,
+                    start: start,
+                    end: end,
+                })
+            }
+            _ => return None,
+        }
+    }
+    if working_hours.is_empty() {
+        return None;
+    }
+    Some(working_hours)
+}
+
+pub fn parse_schedule(s: &str) -> Option<Schedule> {
+    lazy_static! {
+        static ref SCHEDULE_RE: Regex = Regex::new(r"(?x)
+            ^ (?P<days>.+?) \s+ (at \s+)? (?P<hr>\d{1,2}):(?P<mins>\d{2}) \s+ for \s+ (?P<dur>.+) $").unwrap();
+    }
+
+    let s = s.trim().to_lowercase();
+    let caps = match SCHEDULE_RE.captures(&s) {
+        Some(caps) => caps,
+        None => return None,
+    };
+    let days = match parse_days(caps.name("days").unwrap()) {
+        Some(days) => days,
+        None => return None,
+    };
+    let hour = caps.name("hr").unwrap().parse().unwrap();
+    let minute = caps.name("mins").unwrap().parse().unwrap();
+    if hour > 23 || minute > 59 {
+        return None;
+    }
+    let duration = match parse_duration(caps.name("dur").unwrap()) {
+        Some(duration) => duration,
+        None => return None,
+    };
+    Some(Schedule {
+        days: days,
+        hour: hour,
+        minute: minute,
+        duration: duration,
+    })
+}
@@ -0,0 +1,84 @@
+//! The text format `timmy edit` lists periods in for editing, one per line:
+//! `<id> <project> <start> <end> <description>`.
+
+use chrono::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    /// None for a period that is being added
+    pub id: Option<i64>,
+    pub project: String,
+    pub start: DateTime<Local>,
+    pub end: DateTime<Local>,
+    pub description: Option<String>,
+}
+
+pub const TIME_FORMAT: &'static str = "%d/%m/%y %H:%M";
+
+/// Puts a project name in double quotes if it wouldn't otherwise be read back as one word.
+fn quote(project: &str) -> String {
+    if !project.is_empty() && !project.contains(char::is_whitespace) && !project.starts_with('"') {
+        return project.into();
+    }
+    format!("\"{}\"", project.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Reads a possibly quoted project name off the front of `s`, returning it and the rest of `s`.
+fn unquote(s: &str) -> Option<(String, &str)> {
+    let s = s.trim_left();
+    if !s.starts_with('"') {
+        let end = s.find(char::is_whitespace).unwrap_or(s.len());
+        return Some((s[..end].into(), &s[end..]));
+    }
+    let mut project = String::new();
+    let mut chars = s.char_indices().skip(1);
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Some((project, &s[i + 1..])),
+            '\\' => project.push(chars.next()?.1),
+            c => project.push(c),
+        }
+    }
+    None
+}
+
+pub fn render(line: &Line) -> String {
+    let id = match line.id {
+        Some(id) => format!("{}", id),
+        None => "new".into(),
+    };
+    format!("{} {} {} {} {}",
+            id,
+            quote(&line.project),
+            line.start.format(TIME_FORMAT),
+            line.end.format(TIME_FORMAT),
+            line.description.clone().unwrap_or("".into())).trim().into()
+}
+
+/// Reads a line written by `render`, or by hand in the same format.
+pub fn parse(line: &str) -> Option<Line> {
+    let line = line.trim();
+    let (id, rest) = line.split_at(line.find(char::is_whitespace)?);
+    let id = match id {
+        "new" => None,
+        id => Some(id.parse().ok()?),
+    };
+    let (project, rest) = unquote(rest)?;
+    let words: Vec<&str> = rest.split_whitespace().collect();
+    if project.is_empty() || words.len() < 4 {
+        return None;
+    }
+    let parse_time = |date: &str, time: &str| {
+        NaiveDateTime::parse_from_str(&format!("{} {}", date, time), TIME_FORMAT)
+            .ok()
+            .and_then(|t| Local.from_local_datetime(&t).earliest())
+    };
+    let description = words[4..].join(" ");
+    Some(Line {
+        id: id,
+        project: project,
+        start: parse_time(words[0], words[1])?,
+        end: parse_time(words[2], words[3])?,
+        description: if description.is_empty() { None } else { Some(description) },
+    })
+}
@@ -0,0 +1,90 @@
+//! Looking at the order the tracked time was spent in, rather than just the totals.
+
+use chrono::*;
+use probe::Segment;
+
+/// Time spent in one program without switching to another.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stretch {
+    pub program: String,
+    pub start: DateTime<Local>,
+    pub end: DateTime<Local>,
+}
+
+impl Stretch {
+    pub fn duration(&self) -> Duration {
+        self.end - self.start
+    }
+}
+
+/// Joins segments that follow on from each other in the same program, so changing tabs or files
+/// doesn't break up a stretch. Segments are expected in the order they happened, and ones less than
+/// a second apart count as following on, as polling leaves small gaps between them. Estimated
+/// segments are left out since they don't say when a program was used.
+pub fn timeline(segments: &[Segment]) -> Vec<Stretch> {
+    let mut stretches: Vec<Stretch> = vec![];
+    for segment in segments.iter().filter(|segment| !segment.estimated) {
+        if let Some(last) = stretches.last_mut() {
+            if last.program == segment.program && segment.start - last.end < Duration::seconds(1) {
+                if segment.end > last.end {
+                    last.end = segment.end;
+                }
+                continue;
+            }
+        }
+        stretches.push(Stretch {
+            program: segment.program.clone(),
+            start: segment.start,
+            end: segment.end,
+        });
+    }
+    stretches
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metrics {
+    /// How many times focus moved to a different program
+    pub switches: usize,
+    pub longest: Option<Stretch>,
+    /// The time spent in the focus programs
+    pub focus: Duration,
+    pub total: Duration,
+}
+
+impl Metrics {
+    /// The percentage of the time spent in the focus programs.
+    pub fn focus_share(&self) -> f64 {
+        if self.total == Duration::zero() {
+            return 0.0;
+        }
+        self.focus.num_milliseconds() as f64 / self.total.num_milliseconds() as f64 * 100.0
+    }
+}
+
+/// Works out how often focus switched and how much of it went to `focus_programs`, from the
+/// segments that aren't estimated.
+pub fn metrics(segments: &[Segment], focus_programs: &[String]) -> Metrics {
+    let stretches = timeline(segments);
+    let switches = stretches.windows(2).filter(|pair| pair[0].program != pair[1].program).count();
+    let mut longest: Option<Stretch> = None;
+    for stretch in stretches {
+        if longest.as_ref().map_or(true, |longest| stretch.duration() > longest.duration()) {
+            longest = Some(stretch);
+        }
+    }
+    let mut focus = Duration::zero();
+    let mut total = Duration::zero();
+    for segment in segments.iter().filter(|segment| !segment.estimated) {
+        let time = segment.end - segment.start;
+        if focus_programs.contains(&segment.program) {
+            focus = focus + time;
+        }
+        total = total + time;
+    }
+    Metrics {
+        switches: switches,
+        longest: longest,
+        focus: focus,
+        total: total,
+    }
+}
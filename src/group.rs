@@ -0,0 +1,21 @@
+//! Rolling programs that are really the same tool, eg. `code` and `codium`, up under one name.
+
+use probe::Segment;
+
+/// A program and the tool it is reported as.
+pub struct Alias {
+    pub program: String,
+    pub tool: String,
+}
+
+/// The name `program` is reported under.
+pub fn tool<'a>(aliases: &'a [Alias], program: &'a str) -> &'a str {
+    aliases.iter().find(|alias| alias.program == program).map_or(program, |alias| &alias.tool[..])
+}
+
+/// Renames the program of each segment to the tool it belongs to.
+pub fn apply(aliases: &[Alias], segments: Vec<Segment>) -> Vec<Segment> {
+    segments.into_iter()
+        .map(|segment| Segment { program: tool(aliases, &segment.program).into(), ..segment })
+        .collect()
+}
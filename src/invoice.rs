@@ -0,0 +1,259 @@
+use chrono::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Markdown,
+    Html,
+    Text,
+}
+
+/// Converts an amount of money to cents (or pence, ...), which invoices keep amounts in so that
+/// adding them up can't gain or lose a cent.
+pub fn cents(amount: f64) -> i64 {
+    (amount * 100.0).round() as i64
+}
+
+/// Formats an amount in cents with two decimal places, eg. "16.50".
+pub fn format_cents(cents: i64) -> String {
+    let sign = if cents < 0 { "-" } else { "" };
+    format!("{}{}.{:02}", sign, cents.abs() / 100, cents.abs() % 100)
+}
+
+/// The time billed for one project on an invoice.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    pub project: String,
+    pub hours: f64,
+    /// In cents per hour
+    pub rate: i64,
+}
+
+impl Line {
+    /// The hours billed, in hundredths of an hour as they are shown on the invoice.
+    pub fn billed_hours(&self) -> i64 {
+        (self.hours * 100.0).round() as i64
+    }
+
+    /// The amount in cents for the hours as they are shown, rounded to the nearest cent, so that
+    /// the hours times the rate on the invoice comes to the amount.
+    pub fn amount(&self) -> i64 {
+        (self.billed_hours() * self.rate + 50) / 100
+    }
+}
+
+/// An expense being passed on to the customer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expense {
+    pub date: NaiveDate,
+    pub project: String,
+    pub category: Option<String>,
+    pub note: Option<String>,
+    /// In cents
+    pub amount: i64,
+}
+
+impl Expense {
+    fn description(&self) -> String {
+        match (&self.category, &self.note) {
+            (&Some(ref category), &Some(ref note)) => format!("{}: {}", category, note),
+            (&Some(ref category), &None) => category.clone(),
+            (&None, &Some(ref note)) => note.clone(),
+            (&None, &None) => "".into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Invoice {
+    pub number: i64,
+    /// Whether the number is only a guess at the one the invoice will get, as for a dry run
+    pub provisional: bool,
+    pub customer: String,
+    pub date: NaiveDate,
+    pub since: NaiveDate,
+    pub until: NaiveDate,
+    pub currency: Option<String>,
+    pub lines: Vec<Line>,
+    pub expenses: Vec<Expense>,
+}
+
+/// Lays out rows in columns under a rule, right aligning the columns from `right_from` onwards.
+fn text_table(headers: &[String; 4], rows: &[[String; 4]], right_from: usize) -> String {
+    let widths: Vec<usize> = (0..4)
+        .map(|i| rows.iter().chain(Some(headers)).map(|row| row[i].chars().count()).max().unwrap())
+        .collect();
+    let format_row = |row: &[String; 4]| {
+        let mut line = String::new();
+        for i in 0..4 {
+            if i > 0 {
+                line.push_str("  ");
+            }
+            if i < right_from {
+                line.push_str(&format!("{:<1$}", row[i], widths[i]));
+            } else {
+                line.push_str(&format!("{:>1$}", row[i], widths[i]));
+            }
+        }
+        line.push('\n');
+        line
+    };
+    let rule: String = ::std::iter::repeat("-").take(widths.iter().sum::<usize>() + 6).collect();
+    let mut s = format_row(headers);
+    s.push_str(&format!("{}\n", rule));
+    for row in rows {
+        s.push_str(&format_row(row));
+    }
+    s
+}
+
+fn escape_markdown(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('|', "\\|")
+}
+
+fn markdown_row(row: &[String; 4]) -> String {
+    format!("| {} | {} | {} | {} |\n",
+            escape_markdown(&row[0]),
+            escape_markdown(&row[1]),
+            escape_markdown(&row[2]),
+            escape_markdown(&row[3]))
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+impl Invoice {
+    /// The total in cents.
+    pub fn total(&self) -> i64 {
+        self.lines.iter().map(|line| line.amount()).sum::<i64>() +
+        self.expenses.iter().map(|expense| expense.amount).sum::<i64>()
+    }
+
+    pub fn title(&self) -> String {
+        if self.provisional {
+            format!("Invoice {:04} (provisional number)", self.number)
+        } else {
+            format!("Invoice {:04}", self.number)
+        }
+    }
+
+    fn money(&self, cents: i64) -> String {
+        let amount = format_cents(cents);
+        match self.currency {
+            Some(ref currency) => format!("{} {}", amount, currency),
+            None => amount,
+        }
+    }
+
+    fn header(&self) -> Vec<String> {
+        vec![format!("Customer: {}", self.customer),
+             format!("Date: {}", self.date.format("%d/%m/%Y")),
+             format!("Period: {} - {}", self.since.format("%d/%m/%Y"), self.until.format("%d/%m/%Y"))]
+    }
+
+    fn rows(&self) -> Vec<[String; 4]> {
+        self.lines
+            .iter()
+            .map(|line| {
+                [line.project.clone(),
+                 format_cents(line.billed_hours()),
+                 self.money(line.rate),
+                 self.money(line.amount())]
+            })
+            .collect()
+    }
+
+    fn expense_rows(&self) -> Vec<[String; 4]> {
+        self.expenses
+            .iter()
+            .map(|expense| {
+                [format!("{}", expense.date.format("%d/%m/%Y")),
+                 expense.project.clone(),
+                 expense.description(),
+                 self.money(expense.amount)]
+            })
+            .collect()
+    }
+
+    pub fn render(&self, format: Format) -> String {
+        match format {
+            Format::Markdown => self.markdown(),
+            Format::Html => self.html(),
+            Format::Text => self.text(),
+        }
+    }
+
+    fn markdown(&self) -> String {
+        let mut s = format!("# {}\n\n", self.title());
+        for line in self.header() {
+            s.push_str(&format!("{}  \n", line));
+        }
+        if !self.lines.is_empty() {
+            s.push_str("\n| Project | Hours | Rate | Amount |\n|---|---:|---:|---:|\n");
+            for row in self.rows() {
+                s.push_str(&markdown_row(&row));
+            }
+        }
+        if !self.expenses.is_empty() {
+            s.push_str("\n## Expenses\n\n| Date | Project | Description | Amount |\n|---|---|---|---:|\n");
+            for row in self.expense_rows() {
+                s.push_str(&markdown_row(&row));
+            }
+        }
+        s.push_str(&format!("\n**Total: {}**\n", self.money(self.total())));
+        s
+    }
+
+    fn html(&self) -> String {
+        let mut s = format!("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+                             <title>{0}</title>\n</head>\n<body>\n<h1>{0}</h1>\n<p>\n",
+                            self.title());
+        s.push_str(&self.header().iter().map(|line| escape_html(line)).collect::<Vec<_>>().join("<br>\n"));
+        s.push_str("\n</p>\n");
+        if !self.lines.is_empty() {
+            s.push_str("<table>\n<tr><th>Project</th><th>Hours</th><th>Rate</th><th>Amount</th></tr>\n");
+            for row in self.rows() {
+                s.push_str(&format!("<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                                    escape_html(&row[0]),
+                                    row[1],
+                                    escape_html(&row[2]),
+                                    escape_html(&row[3])));
+            }
+            s.push_str("</table>\n");
+        }
+        if !self.expenses.is_empty() {
+            s.push_str("<h2>Expenses</h2>\n<table>\n\
+                        <tr><th>Date</th><th>Project</th><th>Description</th><th>Amount</th></tr>\n");
+            for row in self.expense_rows() {
+                s.push_str(&format!("<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                                    row[0],
+                                    escape_html(&row[1]),
+                                    escape_html(&row[2]),
+                                    escape_html(&row[3])));
+            }
+            s.push_str("</table>\n");
+        }
+        s.push_str(&format!("<p><strong>Total: {}</strong></p>\n</body>\n</html>\n",
+                            escape_html(&self.money(self.total()))));
+        s
+    }
+
+    fn text(&self) -> String {
+        let mut s = format!("{}\n\n", self.title());
+        for line in self.header() {
+            s.push_str(&format!("{}\n", line));
+        }
+        if !self.lines.is_empty() {
+            let headers = ["Project".to_string(), "Hours".into(), "Rate".into(), "Amount".into()];
+            s.push('\n');
+            s.push_str(&text_table(&headers, &self.rows(), 1));
+        }
+        if !self.expenses.is_empty() {
+            let headers = ["Date".to_string(), "Project".into(), "Description".into(), "Amount".into()];
+            s.push_str("\nExpenses\n\n");
+            s.push_str(&text_table(&headers, &self.expense_rows(), 3));
+        }
+        s.push_str(&format!("\nTotal: {}\n", self.money(self.total())));
+        s
+    }
+}
@@ -4,3 +4,13 @@ extern crate chrono;
 
 pub mod tables;
 pub mod chronny;
+pub mod rounding;
+pub mod invoice;
+pub mod probe;
+pub mod category;
+pub mod pattern;
+pub mod privacy;
+pub mod focus;
+pub mod group;
+pub mod suggest;
+pub mod edit;
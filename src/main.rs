@@ -6,29 +6,35 @@ extern crate timmy;
 extern crate log;
 extern crate env_logger;
 
-#[macro_use]
-extern crate lazy_static;
-
 extern crate clap;
 extern crate rusqlite;
 extern crate chrono;
 extern crate ansi_term;
-extern crate regex;
 
 use std::{fs, env, io, time, thread};
-use std::process::Stdio;
-use std::collections::HashMap;
-use std::sync::mpsc::{Receiver, channel};
-use std::path::Path;
+use std::io::{Read, Write};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, channel};
+use std::path::{Path, PathBuf};
 use std::convert::From;
-use std::process::Command;
-use clap::{Arg, App, SubCommand};
+use std::process::{self, Command};
+use std::os::unix::fs::OpenOptionsExt;
+use clap::{Arg, ArgGroup, App, AppSettings, SubCommand};
 use rusqlite::{Connection, Statement};
-use regex::Regex;
+use rusqlite::types::ToSql;
 use chrono::*;
 use ansi_term::Style;
 use timmy::tables::*;
 use timmy::chronny;
+use timmy::rounding::{self, Rounding};
+use timmy::invoice::{self, Invoice};
+use timmy::probe::{self, Headless, ProgramTracker, Segment, Tracker};
+use timmy::category;
+use timmy::pattern::Pattern;
+use timmy::privacy;
+use timmy::focus;
+use timmy::group;
+use timmy::suggest::{self, Drift};
+use timmy::edit::{self, Line as EditLine};
 
 #[derive(Debug)]
 enum Error {
@@ -37,7 +43,37 @@ enum Error {
     Sqlite(rusqlite::Error),
     Git,
     InvalidDateTime(String),
+    InvalidDuration(String),
     InactiveProject(String),
+    PeriodNotFound(i64),
+    DifferentProjects,
+    MergeWithItself(i64),
+    EndBeforeStart(DateTime<Local>, DateTime<Local>),
+    SplitOutsidePeriod(String),
+    InvalidEditLine(String),
+    InvalidSchedule(String),
+    RecurringNotFound(i64),
+    Editor(String),
+    Overlap(Vec<(String, DateTime<Local>, DateTime<Local>)>),
+    PeriodCovered,
+    InvalidRate(String),
+    NoRate(String),
+    NothingToInvoice(String),
+    Io(io::Error),
+    PeriodLocked(i64),
+    InvoiceNotFound(i64),
+    InvalidAmount(String),
+    ExpenseNotFound(i64),
+    ExpenseInvoiced(i64),
+    InvalidHours(String),
+    TimeOffNotFound(i64),
+    InvalidWorkingHours(String),
+    InvalidPattern(String),
+    CategoryNotFound(i64),
+    PrivacyRuleNotFound(i64),
+    ProgramNotGrouped(String),
+    NoProjectForDirectory,
+    ProjectRuleNotFound(i64),
 }
 
 impl From<rusqlite::Error> for Error {
@@ -46,6 +82,12 @@ impl From<rusqlite::Error> for Error {
     }
 }
 
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
 fn open_connection() -> Result<Connection, Error> {
     let home = env::var("HOME").unwrap_or("./".into());
     let path = Path::new(&home).join(".timmy");
@@ -81,9 +123,133 @@ fn open_connection() -> Result<Connection, Error> {
                        CREATE TABLE IF NOT EXISTS program_usage (
                             project_id    INTEGER NOT NULL,
                             program       TEXT NOT NULL,
-                            time          INTEGER NOT NULL);")?;
+                            time          INTEGER NOT NULL);
+
+                       CREATE TABLE IF NOT EXISTS program_segments (
+                            timeperiod_id INTEGER NOT NULL,
+                            program       TEXT NOT NULL,
+                            start         DATETIME NOT NULL,
+                            end           DATETIME NOT NULL);
+
+                       CREATE TABLE IF NOT EXISTS categories (
+                            id            INTEGER PRIMARY KEY,
+                            category      TEXT NOT NULL,
+                            program       TEXT,
+                            title         TEXT);
+
+                       CREATE TABLE IF NOT EXISTS privacy (
+                            id            INTEGER PRIMARY KEY,
+                            action        TEXT NOT NULL,
+                            program       TEXT,
+                            title         TEXT);
+
+                       CREATE TABLE IF NOT EXISTS program_groups (
+                            program       TEXT PRIMARY KEY,
+                            tool          TEXT NOT NULL);
+
+                       CREATE TABLE IF NOT EXISTS project_rules (
+                            id            INTEGER PRIMARY KEY,
+                            project_id    INTEGER NOT NULL,
+                            kind          TEXT NOT NULL,
+                            value         TEXT NOT NULL);
+
+                       CREATE TABLE IF NOT EXISTS pomodoros (
+                            project_id    INTEGER NOT NULL,
+                            start         DATETIME NOT NULL,
+                            end           DATETIME NOT NULL);
+
+                       CREATE TABLE IF NOT EXISTS recurring (
+                            id            INTEGER PRIMARY KEY,
+                            project_id    INTEGER NOT NULL,
+                            description   TEXT,
+                            schedule      TEXT NOT NULL,
+                            since         DATETIME NOT NULL);
+
+                       CREATE TABLE IF NOT EXISTS recurring_occurrences (
+                            recurring_id  INTEGER NOT NULL,
+                            start         DATETIME NOT NULL,
+                            UNIQUE(recurring_id, start));
+
+                       CREATE TABLE IF NOT EXISTS rounding (
+                            project_id    INTEGER UNIQUE,
+                            customer      TEXT UNIQUE,
+                            increment     INTEGER NOT NULL,
+                            per           TEXT NOT NULL,
+                            mode          TEXT NOT NULL);
+
+                       CREATE TABLE IF NOT EXISTS rates (
+                            project_id    INTEGER UNIQUE,
+                            customer      TEXT UNIQUE,
+                            rate          REAL NOT NULL);
+
+                       CREATE TABLE IF NOT EXISTS invoices (
+                            id            INTEGER PRIMARY KEY,
+                            customer      TEXT NOT NULL,
+                            date          DATETIME NOT NULL,
+                            since         DATETIME NOT NULL,
+                            until         DATETIME NOT NULL,
+                            currency      TEXT,
+                            total         REAL NOT NULL);
+
+                       CREATE TABLE IF NOT EXISTS invoiced_periods (
+                            invoice_id    INTEGER NOT NULL,
+                            timeperiod_id INTEGER NOT NULL UNIQUE);
+
+                       CREATE TABLE IF NOT EXISTS expenses (
+                            id            INTEGER PRIMARY KEY,
+                            project_id    INTEGER NOT NULL,
+                            date          DATETIME NOT NULL,
+                            amount        REAL NOT NULL,
+                            currency      TEXT,
+                            category      TEXT,
+                            note          TEXT);
+
+                       CREATE TABLE IF NOT EXISTS invoiced_expenses (
+                            invoice_id    INTEGER NOT NULL,
+                            expense_id    INTEGER NOT NULL UNIQUE);
+
+                       CREATE TABLE IF NOT EXISTS targets (
+                            project_id    INTEGER UNIQUE,
+                            hours         REAL NOT NULL,
+                            since         DATETIME);
+
+                       CREATE TABLE IF NOT EXISTS settings (
+                            key           TEXT PRIMARY KEY,
+                            value         TEXT NOT NULL);
+
+                       CREATE TABLE IF NOT EXISTS timeoff (
+                            id            INTEGER PRIMARY KEY,
+                            start         DATE NOT NULL,
+                            end           DATE NOT NULL,
+                            type          TEXT NOT NULL,
+                            note          TEXT);")?;
 
     let _ = conn.execute("ALTER TABLE projects ADD COLUMN active BOOLEAN NOT NULL DEFAULT 1;", &[]);
+    let _ = conn.execute("ALTER TABLE commits ADD COLUMN time DATETIME;", &[]);
+    // Set while a track is running, so that a session whose process died can be found again
+    let _ = conn.execute("ALTER TABLE timeperiods ADD COLUMN heartbeat DATETIME;", &[]);
+    // A project's billable flag is the default for the periods tracked against it
+    let _ = conn.execute("ALTER TABLE projects ADD COLUMN billable BOOLEAN NOT NULL DEFAULT 1;", &[]);
+    let _ = conn.execute("ALTER TABLE timeperiods ADD COLUMN billable BOOLEAN NOT NULL DEFAULT 1;", &[]);
+    // Invoiced periods are locked so that what was billed can't change by accident
+    if conn.execute("ALTER TABLE timeperiods ADD COLUMN locked BOOLEAN NOT NULL DEFAULT 0;", &[]).is_ok() {
+        conn.execute("UPDATE timeperiods SET locked=1 WHERE id IN (SELECT timeperiod_id FROM invoiced_periods)", &[])?;
+    }
+    let _ = conn.execute("ALTER TABLE invoices ADD COLUMN status TEXT NOT NULL DEFAULT 'draft';", &[]);
+    let _ = conn.execute("ALTER TABLE invoices ADD COLUMN due DATETIME;", &[]);
+    // Expenses are kept in cents like invoices; the old amount is still filled in
+    if conn.execute("ALTER TABLE expenses ADD COLUMN amount_cents INTEGER;", &[]).is_ok() {
+        conn.execute("UPDATE expenses SET amount_cents=CAST(ROUND(amount * 100) AS INTEGER)", &[])?;
+    }
+    // Totals are kept in cents like the invoices themselves; the old total is still filled in
+    if conn.execute("ALTER TABLE invoices ADD COLUMN total_cents INTEGER;", &[]).is_ok() {
+        conn.execute("UPDATE invoices SET total_cents=CAST(ROUND(total * 100) AS INTEGER)", &[])?;
+    }
+    let _ = conn.execute("ALTER TABLE program_segments ADD COLUMN title TEXT;", &[]);
+    let _ = conn.execute("ALTER TABLE program_segments ADD COLUMN estimated BOOLEAN NOT NULL DEFAULT 0;", &[]);
+    let _ = conn.execute("ALTER TABLE pomodoros ADD COLUMN timeperiod_id INTEGER;", &[]);
+    // Targets set before this was added count from the first week tracked
+    let _ = conn.execute("ALTER TABLE targets ADD COLUMN since DATETIME;", &[]);
     Ok(conn)
 }
 
@@ -99,18 +265,23 @@ fn format_time(time: f64) -> String {
     }
 }
 
+fn hours(duration: Duration) -> f64 {
+    duration.num_seconds() as f64 / 3600.0
+}
+
 fn create_project(conn: &mut Connection,
                   name: &str,
                   customer: Option<&str>,
-                  tags: &str)
+                  tags: &str,
+                  billable: bool)
                   -> Result<(), Error> {
     match find_project(conn, name) {
         Ok(_) => return Err(Error::ProjectAlreadyExists(name.into())),
         _ => {},
     };
     let tx = conn.transaction()?;
-    tx.execute("INSERT INTO projects(name, customer) VALUES (?,?)",
-               &[&name, &customer])?;
+    tx.execute("INSERT INTO projects(name, customer, billable) VALUES (?,?,?)",
+               &[&name, &customer, &billable])?;
     let proj_id = tx.last_insert_rowid();
     if tags != "" {
         for tag in tags.split(',') {
@@ -136,7 +307,7 @@ fn restart_project(conn: &mut Connection, name: &str) -> Result<(), Error> {
     change_project_status(conn, name, true)
 }
 
-fn find_project(conn: &mut Connection, name: &str) -> Result<(i64, bool), Error> {
+fn find_project(conn: &Connection, name: &str) -> Result<(i64, bool), Error> {
     match conn.query_row("SELECT id, active FROM projects WHERE name=?",
                          &[&name],
                          |row| (row.get(0), row.get(1))) {
@@ -146,113 +317,512 @@ fn find_project(conn: &mut Connection, name: &str) -> Result<(i64, bool), Error>
     }
 }
 
-fn get_current_program() -> String {
-    // Get the X id for the currently displayed window
-    let output = Command::new("xprop").args(&["-root", "_NET_ACTIVE_WINDOW"]).output().unwrap();
-    // parse: _NET_ACTIVE_WINDOW(WINDOW): window id # 0x3e0000a
-    let output = String::from_utf8_lossy(&output.stdout);
-    let win_id = output.split(' ').last().unwrap();
-    // Get the pid
-    let output = Command::new("xprop").args(&["-id", &format!("{}", win_id)]).output().unwrap();
-    let output = String::from_utf8_lossy(&output.stdout);
-    lazy_static! {
-        static ref REGEX: Regex = Regex::new(r"_NET_WM_PID\(CARDINAL\) = (\d+)").unwrap();
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OverlapPolicy {
+    Reject,
+    TrimNew,
+    TrimExisting,
+    Allow,
+}
+
+fn find_overlaps(conn: &Connection,
+                 start: &DateTime<Local>,
+                 end: &DateTime<Local>,
+                 exclude: Option<i64>)
+                 -> Result<Vec<(i64, String, DateTime<Local>, DateTime<Local>)>, Error> {
+    let mut stmnt = conn.prepare("SELECT timeperiods.id, projects.name, start, end FROM timeperiods
+                                  JOIN projects ON projects.id=timeperiods.project_id
+                                  WHERE start < ? AND end > ? AND timeperiods.id != ?
+                                  ORDER BY start")?;
+    let rows = stmnt.query_map(&[end, start, &exclude.unwrap_or(-1)],
+                               |row| (row.get(0), row.get(1), row.get(2), row.get(3)))?;
+    let mut overlaps = vec![];
+    for row in rows {
+        overlaps.push(row?);
     }
-    let caps = REGEX.captures(&output).unwrap();
-    let pid = caps.at(1).unwrap();
-    let output = Command::new("ps").args(&["-ocomm=", &format!("-p{}", pid)]).output().unwrap();
-    String::from_utf8_lossy(&output.stdout).into_owned()
+    Ok(overlaps)
 }
 
-fn program_tracker_thread(rx: Receiver<bool>)
-                          -> Option<thread::JoinHandle<HashMap<String, i64>>>
-{
-    let status = Command::new("xprop").arg("-root").stdout(Stdio::null()).status();
-    match status {
-        Ok(_) => {},
-        Err(_) => return None
+/// Checks a new (or edited) period against every other period, whatever the project, and applies
+/// the policy. Returns the pieces of the new period that should be stored: usually just the
+/// period itself, but trimming the new period around an existing one can leave more than one.
+fn resolve_overlaps(conn: &Connection,
+                    start: DateTime<Local>,
+                    end: DateTime<Local>,
+                    exclude: Option<i64>,
+                    policy: OverlapPolicy)
+                    -> Result<Vec<(DateTime<Local>, DateTime<Local>)>, Error> {
+    if policy == OverlapPolicy::Allow {
+        return Ok(vec![(start, end)]);
+    }
+    let overlaps = find_overlaps(conn, &start, &end, exclude)?;
+    if overlaps.is_empty() {
+        return Ok(vec![(start, end)]);
+    }
+    match policy {
+        OverlapPolicy::Reject => {
+            Err(Error::Overlap(overlaps.into_iter().map(|(_, p, s, e)| (p, s, e)).collect()))
+        }
+        OverlapPolicy::TrimNew => {
+            let mut pieces = vec![];
+            let mut piece_start = start;
+            for &(_, _, s, e) in &overlaps {
+                if s > piece_start {
+                    pieces.push((piece_start, s));
+                }
+                if e > piece_start {
+                    piece_start = e;
+                }
+            }
+            if piece_start < end {
+                pieces.push((piece_start, end));
+            }
+            if pieces.is_empty() {
+                return Err(Error::PeriodCovered);
+            }
+            for &(s, e) in &pieces {
+                println!("Trimmed to {}-{}", s.format("%d/%m/%y %H:%M"), e.format("%H:%M"));
+            }
+            Ok(pieces)
+        }
+        OverlapPolicy::TrimExisting => {
+            for (id, project, s, e) in overlaps {
+                check_unlocked(conn, id)?;
+                if s < start && e > end {
+                    // The new period sits in the middle so the existing one is split in two
+                    reshape_period(conn, id, &[(s, start), (end, e)])?;
+                    println!("Split {} period into {}-{} and {}-{}",
+                             project,
+                             s.format("%d/%m/%y %H:%M"),
+                             start.format("%H:%M"),
+                             end.format("%d/%m/%y %H:%M"),
+                             e.format("%H:%M"));
+                } else if s < start {
+                    reshape_period(conn, id, &[(s, start)])?;
+                    println!("Trimmed {} period to {}-{}", project, s.format("%d/%m/%y %H:%M"), start.format("%H:%M"));
+                } else if e > end {
+                    reshape_period(conn, id, &[(end, e)])?;
+                    println!("Trimmed {} period to {}-{}", project, end.format("%d/%m/%y %H:%M"), e.format("%H:%M"));
+                } else {
+                    conn.execute("DELETE FROM commits WHERE timeperiod_id=?", &[&id])?;
+                    conn.execute("DELETE FROM program_segments WHERE timeperiod_id=?", &[&id])?;
+                    conn.execute("DELETE FROM pomodoros WHERE timeperiod_id=?", &[&id])?;
+                    conn.execute("DELETE FROM timeperiods WHERE id=?", &[&id])?;
+                    println!("Removed {} period {}-{}", project, s.format("%d/%m/%y %H:%M"), e.format("%H:%M"));
+                }
+            }
+            Ok(vec![(start, end)])
+        }
+        OverlapPolicy::Allow => unreachable!(),
+    }
+}
+
+/// Makes the period `id` cover the first of `pieces`, copying it for the rest. Commits made during
+/// a later piece move to that piece's copy, as do pomodoros started during it; commits with no
+/// recorded time stay where they were.
+/// Program segments go to every piece they overlap and are clipped to it when read.
+fn reshape_period(conn: &Connection,
+                  id: i64,
+                  pieces: &[(DateTime<Local>, DateTime<Local>)])
+                  -> Result<(), Error> {
+    let (start, end) = pieces[0];
+    conn.execute("UPDATE timeperiods SET start=?, end=? WHERE id=?", &[&start, &end, &id])?;
+    for &(start, end) in &pieces[1..] {
+        conn.execute("INSERT INTO timeperiods(project_id, start, end, description, billable)
+                      SELECT project_id, ?, ?, description, billable FROM timeperiods WHERE id=?",
+                     &[&start, &end, &id])?;
+        let new_id = conn.last_insert_rowid();
+        conn.execute("UPDATE commits SET timeperiod_id=? WHERE timeperiod_id=? AND time >= ? AND time < ?",
+                     &[&new_id, &id, &start, &end])?;
+        conn.execute("UPDATE pomodoros SET timeperiod_id=? WHERE timeperiod_id=? AND start >= ? AND start < ?",
+                     &[&new_id, &id, &start, &end])?;
+        conn.execute("INSERT INTO program_segments(timeperiod_id, program, title, start, end, estimated)
+                      SELECT ?, program, title, start, end, estimated FROM program_segments
+                      WHERE timeperiod_id=? AND start < ? AND end > ?",
+                     &[&new_id, &id, &end, &start])?;
+    }
+    conn.execute("DELETE FROM program_segments WHERE timeperiod_id=? AND (end <= ? OR start >= ?)",
+                 &[&id, &start, &end])?;
+    Ok(())
+}
+
+fn overlap_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("overlap")
+        .long("overlap")
+        .help("what to do if the period overlaps existing ones: reject (the default), trim-new or \
+               trim-existing")
+        .takes_value(true)
+        .possible_values(&["reject", "trim-new", "trim-existing"])
+}
+
+fn allow_overlap_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("allow overlap")
+        .long("allow-overlap")
+        .help("store the period even if it overlaps existing ones")
+        .conflicts_with("overlap")
+}
+
+fn overlap_policy(matches: &clap::ArgMatches) -> OverlapPolicy {
+    if matches.is_present("allow overlap") {
+        return OverlapPolicy::Allow;
+    }
+    match matches.value_of("overlap") {
+        Some("trim-new") => OverlapPolicy::TrimNew,
+        Some("trim-existing") => OverlapPolicy::TrimExisting,
+        _ => OverlapPolicy::Reject,
+    }
+}
+
+/// The top of the git repository the current directory is in, or the directory itself.
+fn repository_root() -> Option<PathBuf> {
+    match Command::new("git").args(&["rev-parse", "--show-toplevel"]).output() {
+        Ok(ref output) if output.status.success() => {
+            Some(PathBuf::from(String::from_utf8_lossy(&output.stdout).trim()))
+        }
+        _ => env::current_dir().ok(),
     }
+}
+
+/// Tracks the focused window when there is a display to ask, and otherwise the programs working in
+/// the current repository, which is all there is to go on over SSH. `interval` is how many
+/// milliseconds to wait between looking, if not the default for the kind of tracking. `drift` is
+/// told what has focus each time, to warn when it looks like another project is being worked on.
+fn program_tracker_thread(rx: Receiver<bool>,
+                          interval: Option<u64>,
+                          mut drift: Drift)
+                          -> Option<thread::JoinHandle<Vec<Segment>>>
+{
+    let now = Local::now();
+    let (mut tracker, default_interval): (Box<Tracker + Send>, u64) = match probe::from_env() {
+        Some(probe) => (Box::new(ProgramTracker::new(probe, now)), 100),
+        None => (Box::new(Headless::new(&repository_root()?, now)?), 1000),
+    };
+    let interval = interval.unwrap_or(default_interval);
     let handle = thread::spawn(move || {
-        let mut hm = HashMap::new();
-        let mut current_program = get_current_program().trim().into();
-        let mut program_change_time = Local::now();
         while rx.try_recv().is_err() {
-            let new_program = get_current_program().trim().into();
-            if new_program != current_program {
-                let diff = Local::now() - program_change_time;
-                program_change_time = Local::now();
-                let counter = hm.entry(current_program).or_insert(0);
-                *counter += diff.num_seconds();
-                current_program = new_program;
+            let now = Local::now();
+            tracker.poll(now);
+            if let Some(project) = drift.observe(tracker.current(), now) {
+                println!("The windows you've been using for the last {} minutes belong to {}, are you \
+                          tracking the right project?",
+                         DRIFT_MINS,
+                         project);
             }
-            thread::sleep(time::Duration::from_millis(100));
+            thread::sleep(time::Duration::from_millis(interval));
         }
-        return hm;
+        tracker.finish(Local::now())
     });
     Some(handle)
 }
 
+/// How long focus has to stay on another project's windows before the tracker warns about it.
+const DRIFT_MINS: i64 = 15;
+
+const HEARTBEAT_SECS: u64 = 30;
+
+/// Keeps the end of a running session up to date until told to stop. Uses its own connection as
+/// the main one is busy waiting for the session to finish.
+fn heartbeat_thread(period_id: i64, rx: Receiver<()>) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let conn = match open_connection() {
+            Ok(conn) => conn,
+            Err(e) => {
+                debug!("heartbeat couldn't open the database: {:?}", e);
+                return;
+            }
+        };
+        while let Err(RecvTimeoutError::Timeout) = rx.recv_timeout(time::Duration::from_secs(HEARTBEAT_SECS)) {
+            let now = Local::now();
+            if let Err(e) = conn.execute("UPDATE timeperiods SET end=?, heartbeat=? WHERE id=?",
+                                         &[&now, &now, &period_id]) {
+                debug!("heartbeat failed: {:?}", e);
+            }
+        }
+    })
+}
+
+/// Finds sessions whose track process stopped sending heartbeats without finishing and asks what to
+/// do with each of them. The questions go to stderr, and when there is no terminal to answer them
+/// on the sessions are left for later.
+fn recover_sessions(conn: &mut Connection) -> Result<(), Error> {
+    let stale = Local::now() - Duration::seconds(3 * HEARTBEAT_SECS as i64);
+    let sessions: Vec<(i64, String, DateTime<Local>, DateTime<Local>)> = {
+        let mut stmnt = conn.prepare("SELECT timeperiods.id, projects.name, start, heartbeat
+                                      FROM timeperiods
+                                      JOIN projects ON projects.id=timeperiods.project_id
+                                      WHERE heartbeat IS NOT NULL AND heartbeat < ?")?;
+        let rows = stmnt.query_map(&[&stale], |row| (row.get(0), row.get(1), row.get(2), row.get(3)))?;
+        let mut sessions = vec![];
+        for row in rows {
+            sessions.push(row?);
+        }
+        sessions
+    };
+    let mut stderr = io::stderr();
+    if !sessions.is_empty() && probe::procfs::Proc::new().tty(process::id()).is_none() {
+        let _ = writeln!(stderr,
+                         "{} unfinished session(s) found, run `timmy recover` in a terminal to deal with them",
+                         sessions.len());
+        return Ok(());
+    }
+    for (id, project, start, heartbeat) in sessions {
+        let _ = writeln!(stderr,
+                         "The session for {} started at {} was never finished. It was last running at {}.",
+                         project,
+                         start.format("%d/%m/%y %H:%M"),
+                         heartbeat.format("%d/%m/%y %H:%M"));
+        loop {
+            let _ = write!(stderr,
+                           "Close it at that time (c), close it at another time (t), discard it (d) or \
+                            leave it for now (l)? ");
+            let _ = stderr.flush();
+            let mut answer = String::new();
+            if io::stdin().read_line(&mut answer).unwrap() == 0 {
+                // stdin is closed so nobody can answer
+                break;
+            }
+            let end = match answer.trim() {
+                "c" => heartbeat,
+                "t" => {
+                    let _ = write!(stderr, "When did it end? ");
+                    let _ = stderr.flush();
+                    let mut end = String::new();
+                    io::stdin().read_line(&mut end).unwrap();
+                    match parse_end(end.trim(), start) {
+                        Ok(end) if end > start => end,
+                        _ => {
+                            let _ = writeln!(stderr, "Could not parse {}", end.trim());
+                            continue;
+                        }
+                    }
+                }
+                "d" => {
+                    conn.execute("DELETE FROM timeperiods WHERE id=?", &[&id])?;
+                    break;
+                }
+                "l" => break,
+                _ => continue,
+            };
+            // Anything tracked since the session died takes precedence over it
+            let tx = conn.transaction()?;
+            tx.execute("UPDATE timeperiods SET heartbeat=NULL WHERE id=?", &[&id])?;
+            match resolve_overlaps(&tx, start, end, Some(id), OverlapPolicy::TrimNew) {
+                Ok(pieces) => reshape_period(&tx, id, &pieces)?,
+                Err(Error::PeriodCovered) => {
+                    let _ = writeln!(stderr,
+                                     "The session is entirely covered by other periods so it has been discarded");
+                    tx.execute("DELETE FROM timeperiods WHERE id=?", &[&id])?;
+                }
+                Err(e) => return Err(e),
+            }
+            tx.commit()?;
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Parses an end time relative to the start, so that "13:00" is on the same day as the start and
+/// "+90m" is ninety minutes after it.
+fn parse_end(end: &str, start: DateTime<Local>) -> Result<DateTime<Local>, Error> {
+    chronny::parse_end(end, start, Local::now()).ok_or_else(|| if end.starts_with('+') {
+        Error::InvalidDuration(end.into())
+    } else {
+        Error::InvalidDateTime(end.into())
+    })
+}
+
+/// Parses a pomodoro setting like "25m/5m" into the work and break lengths.
+fn parse_pomodoro(s: &str) -> Result<(Duration, Duration), Error> {
+    let mut parts = s.splitn(2, '/');
+    let work = parts.next().and_then(chronny::parse_duration);
+    let rest = parts.next().and_then(chronny::parse_duration);
+    match (work, rest) {
+        (Some(work), Some(rest)) if work > Duration::zero() => Ok((work, rest)),
+        _ => Err(Error::InvalidDuration(s.into())),
+    }
+}
+
+/// Alternates between work and breaks until ENTER is pressed. Returns when that was, the completed
+/// pomodoros and the breaks taken.
+fn run_pomodoros(work: Duration,
+                 rest: Duration)
+                 -> (DateTime<Local>,
+                     Vec<(DateTime<Local>, DateTime<Local>)>,
+                     Vec<(DateTime<Local>, DateTime<Local>)>) {
+    let (tx, rx) = channel();
+    thread::spawn(move || {
+        let mut s = String::new();
+        let _ = io::stdin().read_line(&mut s);
+        let _ = tx.send(());
+    });
+    let to_std = |d: Duration| time::Duration::from_secs(d.num_seconds() as u64);
+    let mut pomodoros = vec![];
+    let mut breaks = vec![];
+    loop {
+        let work_start = Local::now();
+        if rx.recv_timeout(to_std(work)).is_ok() {
+            return (Local::now(), pomodoros, breaks);
+        }
+        pomodoros.push((work_start, Local::now()));
+        println!("\x07Pomodoro {} done, take a {} break. Press ENTER to finish",
+                 pomodoros.len(),
+                 format_time(rest.num_seconds() as f64 / 3600.0));
+        let break_start = Local::now();
+        let finished = rx.recv_timeout(to_std(rest)).is_ok();
+        let break_end = Local::now();
+        breaks.push((break_start, break_end));
+        if finished {
+            return (break_end, pomodoros, breaks);
+        }
+        println!("\x07Break over, back to work. Press ENTER to finish");
+    }
+}
+
 fn track(conn: &mut Connection,
-         name: &str,
+         name: Option<&str>,
          description: Option<&str>,
          start: Option<&str>,
          end: Option<&str>,
-         no_program: bool) -> Result<(), Error> {
+         duration: Option<&str>,
+         pomodoro: Option<&str>,
+         billable: Option<bool>,
+         no_program: bool,
+         policy: OverlapPolicy) -> Result<(), Error> {
+    recover_sessions(conn)?;
+    let pomodoro = if let Some(pomodoro) = pomodoro {
+        Some(parse_pomodoro(pomodoro)?)
+    } else {
+        None
+    };
+    let name = match name {
+        Some(name) => name.to_string(),
+        None => {
+            let name = suggest_project(conn)?;
+            println!("Tracking {}", name);
+            name
+        }
+    };
+    let name = &name[..];
     let (proj_id, active) = find_project(conn, name)?;
     if !active {
         return Err(Error::InactiveProject(name.into()));
     }
+    let billable = match billable {
+        Some(billable) => billable,
+        None => conn.query_row("SELECT billable FROM projects WHERE id=?", &[&proj_id], |row| row.get(0))?,
+    };
+    let duration = if let Some(duration) = duration {
+        Some(chronny::parse_duration(duration).ok_or(Error::InvalidDuration(duration.into()))?)
+    } else {
+        None
+    };
     let start = if let Some(start) = start {
         chronny::parse_datetime(start, Local::now()).ok_or(Error::InvalidDateTime(start.into()))?
+    } else if let Some(duration) = duration {
+        Local::now() - duration
     } else {
         Local::now()
     };
     println!("Starting at {}", start.format("%d/%m/%y %H:%M"));
-    let (end, times) = if let Some(end) = end {
-        (chronny::parse_datetime(end, Local::now()).ok_or(Error::InvalidDateTime(end.into()))?,
-         HashMap::new())
+    let mut pomodoros = vec![];
+    let mut breaks = vec![];
+    let (end, segments, session_id) = if let Some(duration) = duration {
+        check_times(start, start + duration)?;
+        (start + duration, vec![], None)
+    } else if let Some(end) = end {
+        let end = parse_end(end, start)?;
+        check_times(start, end)?;
+        (end, vec![], None)
     } else {
+        // Store the period straight away so that it survives the process being killed
+        conn.execute("INSERT INTO timeperiods(project_id, start, end, description, billable, heartbeat)
+                      VALUES (?,?,?,?,?,?)",
+                     &[&proj_id, &start, &start, &description, &billable, &Local::now()])?;
+        let session_id = conn.last_insert_rowid();
+        let (heartbeat_tx, heartbeat_rx) = channel();
+        let heartbeat_handle = heartbeat_thread(session_id, heartbeat_rx);
         let (tx, rx) = channel();
-        let handle = program_tracker_thread(rx);
+        let drift = Drift::new(project_rules(conn)?, name, Duration::minutes(DRIFT_MINS));
+        let handle = program_tracker_thread(rx, sample_interval(conn)?, drift);
         println!("When you are finished with the task press ENTER");
-        let mut s = String::new();
-        io::stdin().read_line(&mut s).unwrap();
+        let end = if let Some((work, rest)) = pomodoro {
+            let (end, done, taken) = run_pomodoros(work, rest);
+            pomodoros = done;
+            breaks = taken;
+            end
+        } else {
+            let mut s = String::new();
+            io::stdin().read_line(&mut s).unwrap();
+            Local::now()
+        };
         let _ = tx.send(true);
-        let mut times = HashMap::new();
+        let _ = heartbeat_tx.send(());
+        let _ = heartbeat_handle.join();
+        let mut segments = vec![];
         if let Some(handle) = handle {
             if !no_program {
-                times = handle.join().expect("couldn't join program tracker thread");
+                segments = handle.join().expect("couldn't join program tracker thread");
             }
         }
-        debug!("program times: {:?}", times);
-        (Local::now(), times)
+        debug!("program segments: {:?}", segments);
+        (end, segments, Some(session_id))
     };
     println!("Ending at {}", end.format("%d/%m/%y %H:%M"));
+    let segments = probe::settle(segments, min_dwell(conn)?);
+    let segments = privacy::apply(&privacy_rules(conn)?, segments);
 
     let tx = conn.transaction()?;
-    tx.execute("INSERT INTO timeperiods(project_id, start, end, description) VALUES (?,?,?,?)",
-                &[&proj_id, &start, &end, &description])?;
-    let period_id = tx.last_insert_rowid();
+    if let Some(session_id) = session_id {
+        tx.execute("DELETE FROM timeperiods WHERE id=?", &[&session_id])?;
+    }
+    // breaks aren't worked time, so the session is stored as the stretches of work between them
+    let mut work = vec![];
+    let mut work_start = start;
+    for &(break_start, break_end) in &breaks {
+        if break_start > work_start {
+            work.push((work_start, break_start));
+        }
+        work_start = break_end;
+    }
+    if work_start < end {
+        work.push((work_start, end));
+    }
+    let mut pieces = vec![];
+    for (start, end) in work {
+        pieces.append(&mut resolve_overlaps(&tx, start, end, None, policy)?);
+    }
     {
-        let mut stmnt = tx.prepare("INSERT INTO commits (sha, summary, project_id, timeperiod_id) \
-                                    values(?,?,?,?)")?;
-        match get_commits(&mut stmnt, proj_id, period_id, &start, &end) {
-            Ok(()) => {},
-            Err(Error::Git) => println!("Git either isn't installed or there is no repo in the \
-                                         current working directory. To associate commits with this \
-                                         project run `timmy git <project>` in a directory with a \
-                                         git repo."),
-            e => return e,
-        };
-        let mut stmnt = tx.prepare("INSERT INTO program_usage(project_id, program, time) VALUES (?,?,?)")?;
-        for (program, time) in &times {
-            let old_time: i64 = tx.query_row("SELECT time FROM program_usage WHERE project_id=? AND program=?",
-                                             &[&proj_id, program],
-                                             |row| row.get(0))
-                                  .unwrap_or(0);
-            tx.execute("DELETE FROM program_usage WHERE project_id=? AND program=?", &[&proj_id, program])?;
-            let time = time + old_time;
-            stmnt.execute(&[&proj_id, program, &time])?;
+        let mut stmnt = tx.prepare("INSERT INTO commits (sha, summary, project_id, timeperiod_id, time) \
+                                    values(?,?,?,?,?)")?;
+        let mut git_missing = false;
+        for (start, end) in pieces {
+            tx.execute("INSERT INTO timeperiods(project_id, start, end, description, billable)
+                        VALUES (?,?,?,?,?)",
+                       &[&proj_id, &start, &end, &description, &billable])?;
+            let period_id = tx.last_insert_rowid();
+            for &(pomodoro_start, pomodoro_end) in &pomodoros {
+                if pomodoro_start >= start && pomodoro_start < end {
+                    tx.execute("INSERT INTO pomodoros(project_id, timeperiod_id, start, end) VALUES (?,?,?,?)",
+                               &[&proj_id, &period_id, &pomodoro_start, &pomodoro_end])?;
+                }
+            }
+            for segment in segments.iter().filter_map(|segment| segment.clip(start, end)) {
+                tx.execute("INSERT INTO program_segments(timeperiod_id, program, title, start, end, estimated)
+                            VALUES (?,?,?,?,?,?)",
+                           &[&period_id, &segment.program, &segment.title, &segment.start, &segment.end,
+                             &segment.estimated])?;
+            }
+            match get_commits(&mut stmnt, proj_id, period_id, &start, &end) {
+                Ok(()) => {},
+                Err(Error::Git) => git_missing = true,
+                e => return e,
+            };
+        }
+        if git_missing {
+            println!("Git either isn't installed or there is no repo in the current working \
+                      directory. To associate commits with this project run `timmy git <project>` \
+                      in a directory with a git repo.");
         }
     }
     tx.commit()?;
@@ -261,7 +831,9 @@ fn track(conn: &mut Connection,
 
 fn get_commits(insert_stmnt: &mut Statement, proj_id: i64, period_id: i64, start: &DateTime<Local>, end: &DateTime<Local>) -> Result<(), Error> {
     let mut cmd = Command::new("git");
+    // --since and --until go by the committer date, so that is the date read back too
     cmd.arg("whatchanged")
+        .arg("--pretty=fuller")
         .arg(format!("--since={}", start.to_rfc3339()))
         .arg(format!("--until={}", end.to_rfc3339()))
         .arg("-q");
@@ -284,24 +856,41 @@ fn get_commits(insert_stmnt: &mut Statement, proj_id: i64, period_id: i64, start
         // parses the following:
 
         // commit f04a366b0da4377b2f1e87dc9ec68bdf68c24cee
-        // Author: Matthew Hall <matthew@quickbeam.me.uk>
-        // Date:   Sun Aug 21 15:00:43 2016 +0100
+        // Author:     Matthew Hall <matthew@quickbeam.me.uk>
+        // AuthorDate: Sun Aug 21 14:52:10 2016 +0100
+        // Commit:     Matthew Hall <matthew@quickbeam.me.uk>
+        // CommitDate: Sun Aug 21 15:00:43 2016 +0100
         //
         //     Add total time to project view
 
-        if line.starts_with("commit") {
-            let sha = line.split(' ').nth(1).unwrap();
+        if line.starts_with("commit ") {
+            let sha = match line.split(' ').nth(1) {
+                Some(sha) if !sha.is_empty() => sha,
+                _ => continue,
+            };
             debug!("{}", sha);
-            // skip author
-            lines.next();
-            // skip date
-            lines.next();
-            // skip newline
-            lines.next();
-            // parse summary
-            let summary = lines.next().unwrap().trim();
+            // go through the headers up to the newline, picking out the date
+            let mut time: Option<DateTime<Local>> = None;
+            while let Some(header) = lines.next() {
+                if header.is_empty() {
+                    break;
+                }
+                if header.starts_with("CommitDate:") {
+                    time = DateTime::parse_from_str(header[11..].trim(), "%a %b %e %H:%M:%S %Y %z")
+                        .ok()
+                        .map(|t| t.with_timezone(&Local));
+                }
+            }
+            // parse summary, leaving out a commit whose output was cut short
+            let summary = match lines.next() {
+                Some(summary) => summary.trim(),
+                None => {
+                    debug!("no summary for commit {}", sha);
+                    break;
+                }
+            };
             println!("Found commit {}: {}", sha, summary);
-            insert_stmnt.execute(&[&sha, &summary, &proj_id, &period_id])?;
+            insert_stmnt.execute(&[&sha, &summary, &proj_id, &period_id, &time])?;
         }
     }
     Ok(())
@@ -314,14 +903,17 @@ fn git(conn: &mut Connection, project: &str) -> Result<(), Error> {
     }
     let tx = conn.transaction()?;
 
-    tx.execute("DELETE FROM commits WHERE project_id=?", &[&proj_id])?;
+    // invoiced periods keep the commits they were billed with
+    tx.execute("DELETE FROM commits WHERE project_id=?
+                AND timeperiod_id NOT IN (SELECT id FROM timeperiods WHERE locked)",
+               &[&proj_id])?;
 
     // tx.prepare borrows tx so to call commit stmnt must be dropped
     {
-        let mut stmnt = tx.prepare("SELECT id, start, end FROM timeperiods WHERE project_id=?")?;
+        let mut stmnt = tx.prepare("SELECT id, start, end FROM timeperiods WHERE project_id=? AND NOT locked")?;
         let mut rows = stmnt.query(&[&proj_id])?;
-        let mut insert_stmnt = tx.prepare("INSERT INTO commits (sha, summary, project_id, timeperiod_id) \
-                                           values(?,?,?,?)")?;
+        let mut insert_stmnt = tx.prepare("INSERT INTO commits (sha, summary, project_id, timeperiod_id, time) \
+                                           values(?,?,?,?,?)")?;
         while let Some(row) = rows.next() {
             let row = row?;
             let period_id: i64 = row.get(0);
@@ -362,7 +954,10 @@ fn projects(conn: &mut Connection, all: bool) -> Result<(), Error> {
     Ok(())
 }
 
-fn print_activity(conn: &mut Connection, id: i64, week: bool, since: Option<&str>, until: Option<&str>) -> Result<(), Error> {
+fn parse_range(week: bool,
+               since: Option<&str>,
+               until: Option<&str>)
+               -> Result<(DateTime<Local>, DateTime<Local>), Error> {
     let mut since = if let Some(since) = since {
         debug!("{}", since);
         chronny::parse_datetime(since, Local::now()).ok_or(Error::InvalidDateTime(since.into()))?
@@ -378,10 +973,20 @@ fn print_activity(conn: &mut Connection, id: i64, week: bool, since: Option<&str
     if week {
         since = Local::now() - Duration::days(7);
     }
+    Ok((since, until))
+}
+
+fn print_activity(conn: &mut Connection,
+                  id: i64,
+                  week: bool,
+                  since: Option<&str>,
+                  until: Option<&str>,
+                  rounding: Rounding)
+                  -> Result<(), Error> {
+    let (since, until) = parse_range(week, since, until)?;
     debug!("printing activity between {:?} and {:?}", since, until);
     let mut periods_stmnt =
-        conn.prepare("SELECT id, start, end, description,
-                             CAST((julianday(end)-julianday(start))*24 AS REAL)
+        conn.prepare("SELECT id, start, end, description, billable
                       FROM timeperiods
                       WHERE project_id=? AND start > ? AND start < ?
                       ORDER BY start DESC")?;
@@ -391,26 +996,39 @@ fn print_activity(conn: &mut Connection, id: i64, week: bool, since: Option<&str
     let subtitle_style = Style::new().underline();
     println!("{}", subtitle_style.paint("Activity"));
 
-    let mut total = 0.0f64;
+    let mut billable_periods = vec![];
+    let mut non_billable_periods = vec![];
     for row in rows {
-        let (timeperiod_id, start, end, description, time): (i64,
-                                                             DateTime<Local>,
-                                                             DateTime<Local>,
-                                                             Option<String>,
-                                                             f64) = row?;
-        total += time;
-        let time_string = format_time(time);
+        let (timeperiod_id, start, end, description, billable): (i64,
+                                                                 DateTime<Local>,
+                                                                 DateTime<Local>,
+                                                                 Option<String>,
+                                                                 bool) = row?;
+        if billable {
+            billable_periods.push((start, end));
+        } else {
+            non_billable_periods.push((start, end));
+        }
+        // rounding a day's total can't be shown against a single period
+        let time = if rounding.per == rounding::Per::Period {
+            rounding.round(end - start)
+        } else {
+            end - start
+        };
+        let time_string = format_time(hours(time));
         let description_string = if let Some(desc) = description {
             format!(": {}", desc)
         } else {
             "".into()
         };
+        let billable_string = if billable { "" } else { " (non-billable)" };
         let time_fmt = "%H:%M";
-        println!("{} {}-{} {}{}",
+        println!("{} {}-{} {}{}{}",
                  start.format("%a %d %B %Y"),
                  start.format(time_fmt),
                  end.format(time_fmt),
                  time_string,
+                 billable_string,
                  description_string);
 
         let mut commits_stmnt = conn.prepare("SELECT summary FROM commits WHERE timeperiod_id=?")?;
@@ -420,7 +1038,11 @@ fn print_activity(conn: &mut Connection, id: i64, week: bool, since: Option<&str
             println!("    * {}", msg);
         }
     }
-    println!("Total: {}", format_time(total));
+    let billable = rounding.total(&billable_periods);
+    let non_billable = rounding.total(&non_billable_periods);
+    println!("Billable: {}", format_time(hours(billable)));
+    println!("Non-billable: {}", format_time(hours(non_billable)));
+    println!("Total: {}", format_time(hours(billable + non_billable)));
     Ok(())
 }
 
@@ -428,7 +1050,11 @@ fn print_project_summary(conn: &mut Connection,
                          id: i64,
                          name: &str,
                          customer: Option<String>,
-                         tags: Option<String>)
+                         tags: Option<String>,
+                         rounding: Rounding,
+                         week: bool,
+                         since: Option<&str>,
+                         until: Option<&str>)
                          -> Result<(), Error>
 {
     let title_style = Style::new().underline().bold();
@@ -444,142 +1070,2186 @@ fn print_project_summary(conn: &mut Connection,
         println!("Tags: {}", tags);
     }
 
-    let total_time: Option<f64> =
-        conn.query_row("SELECT SUM(CAST((julianday(end)-julianday(start))*24 as REAL))
-                        FROM timeperiods WHERE project_id=?",
-                       &[&id],
-                       |row| row.get(0))?;
-    let total_time = total_time.unwrap_or(0.0);
-    let total_time_str = format_time(total_time);
-    println!("Total time spent: {}", total_time_str);
+    let billable = rounding.total(&period_times(conn, id, true, None, None)?);
+    let non_billable = rounding.total(&period_times(conn, id, false, None, None)?);
+    println!("Total time spent: {}", format_time(hours(billable + non_billable)));
+    println!("Billable: {}", format_time(hours(billable)));
+    println!("Non-billable: {}", format_time(hours(non_billable)));
+
+    let expenses = expense_totals(conn, id, None, None)?;
+    if !expenses.is_empty() {
+        println!("Expenses: {}", format_amounts(&expenses));
+    }
+
+    // pomodoros from before they were kept per period can't be dated, like program usage
+    let (since_time, until_time) = parse_range(week, since, until)?;
+    let condition = if !week && since.is_none() && until.is_none() {
+        "pomodoros.timeperiod_id IS NULL OR timeperiods.start > ?2 AND timeperiods.start < ?3"
+    } else {
+        "timeperiods.start > ?2 AND timeperiods.start < ?3"
+    };
+    let pomodoros: i64 = conn.query_row(&format!("SELECT COUNT(*) FROM pomodoros
+                                                  LEFT JOIN timeperiods ON timeperiods.id=pomodoros.timeperiod_id
+                                                  WHERE pomodoros.project_id=?1 AND ({})",
+                                                 condition),
+                                        &[&id, &since_time, &until_time],
+                                        |row| row.get(0))?;
+    if pomodoros > 0 {
+        println!("Pomodoros completed: {}", pomodoros);
+    }
     println!("");
     Ok(())
 }
 
-fn print_program_usage(conn: &mut Connection, id: i64, total_time: Option<i64>) -> Result<(), Error> {
-    if let Some(total_time) = total_time {
-        let subtitle_style = Style::new().underline();
-        println!("{}", subtitle_style.paint("Program usage"));
-        let mut stmnt = conn.prepare("SELECT program, time FROM program_usage WHERE project_id=? ORDER BY time DESC")?;
+/// Formats amounts in cents, each in its own currency.
+fn format_amounts(amounts: &[(Option<String>, i64)]) -> String {
+    if amounts.is_empty() {
+        return "None".into();
+    }
+    amounts.iter()
+           .map(|&(ref currency, amount)| match *currency {
+               Some(ref currency) => format!("{} {}", invoice::format_cents(amount), currency),
+               None => invoice::format_cents(amount),
+           })
+           .collect::<Vec<_>>()
+           .join(", ")
+}
+
+/// A project's expenses added up in cents, one total for each currency they were paid in.
+fn expense_totals(conn: &Connection,
+                  project_id: i64,
+                  since: Option<DateTime<Local>>,
+                  until: Option<DateTime<Local>>)
+                  -> Result<Vec<(Option<String>, i64)>, Error> {
+    let mut stmnt = conn.prepare("SELECT currency, SUM(amount_cents) FROM expenses
+                                  WHERE project_id=?1
+                                    AND (?2 IS NULL OR date >= ?2) AND (?3 IS NULL OR date < ?3)
+                                  GROUP BY currency
+                                  ORDER BY currency")?;
+    let rows = stmnt.query_map(&[&project_id, &since, &until], |row| (row.get(0), row.get(1)))?;
+    let mut totals = vec![];
+    for row in rows {
+        totals.push(row?);
+    }
+    Ok(totals)
+}
+
+fn add_amounts(totals: &mut Vec<(Option<String>, i64)>, amounts: &[(Option<String>, i64)]) {
+    for &(ref currency, amount) in amounts {
+        match totals.iter().position(|&(ref c, _)| c == currency) {
+            Some(i) => totals[i].1 += amount,
+            None => totals.push((currency.clone(), amount)),
+        }
+    }
+}
+
+fn print_expenses(conn: &mut Connection,
+                  id: i64,
+                  week: bool,
+                  since: Option<&str>,
+                  until: Option<&str>)
+                  -> Result<(), Error> {
+    let (since, until) = parse_range(week, since, until)?;
+    let mut stmnt = conn.prepare("SELECT date, amount_cents, currency, category, note FROM expenses
+                                  WHERE project_id=? AND date >= ? AND date < ?
+                                  ORDER BY date DESC")?;
+    let rows = stmnt.query_map(&[&id, &since, &until],
+                               |row| (row.get(0), row.get(1), row.get(2), row.get(3), row.get(4)))?;
+    let mut expenses = vec![];
+    for row in rows {
+        let expense: (DateTime<Local>, i64, Option<String>, Option<String>, Option<String>) = row?;
+        expenses.push(expense);
+    }
+    if expenses.is_empty() {
+        return Ok(());
+    }
+    let subtitle_style = Style::new().underline();
+    println!("{}", subtitle_style.paint("Expenses"));
+    let mut totals = vec![];
+    for (date, amount, currency, category, note) in expenses {
+        let description: Vec<String> = category.into_iter().chain(note).collect();
+        println!("{} {}{}",
+                 date.format("%a %d %B %Y"),
+                 format_amounts(&[(currency.clone(), amount)]),
+                 if description.is_empty() { "".into() } else { format!(": {}", description.join(", ")) });
+        add_amounts(&mut totals, &[(currency, amount)]);
+    }
+    println!("Total: {}", format_amounts(&totals));
+    println!("");
+    Ok(())
+}
+
+fn expense_add(conn: &mut Connection,
+               project: &str,
+               amount: &str,
+               date: Option<&str>,
+               currency: Option<&str>,
+               category: Option<&str>,
+               note: Option<&str>)
+               -> Result<(), Error> {
+    let (project_id, _) = find_project(conn, project)?;
+    let amount = match amount.parse::<f64>() {
+        Ok(amount) if amount.is_finite() && amount >= 0.0 => amount,
+        _ => return Err(Error::InvalidAmount(amount.into())),
+    };
+    let date = if let Some(date) = date {
+        chronny::parse_datetime(date, Local::now()).ok_or(Error::InvalidDateTime(date.into()))?
+    } else {
+        Local::now()
+    };
+    conn.execute("INSERT INTO expenses(project_id, date, amount, amount_cents, currency, category, note)
+                  VALUES (?,?,?,?,?,?,?)",
+                 &[&project_id, &date, &amount, &invoice::cents(amount), &currency, &category, &note])?;
+    Ok(())
+}
+
+fn expense_list(conn: &mut Connection,
+                name: Option<&str>,
+                week: bool,
+                since: Option<&str>,
+                until: Option<&str>)
+                -> Result<(), Error> {
+    let (since, until) = parse_range(week, since, until)?;
+    let project_id = match name {
+        Some(name) => Some(find_project(conn, name)?.0),
+        None => None,
+    };
+    let mut stmnt = conn.prepare("SELECT expenses.id, date, projects.name, category, note, amount_cents, currency,
+                                         invoice_id
+                                  FROM expenses
+                                  JOIN projects ON projects.id=expenses.project_id
+                                  LEFT JOIN invoiced_expenses ON invoiced_expenses.expense_id=expenses.id
+                                  WHERE (?1 IS NULL OR project_id=?1) AND date >= ?2 AND date < ?3
+                                  ORDER BY date DESC")?;
+    let rows = stmnt.query_map(&[&project_id, &since, &until],
+                               |row| (row.get(0), row.get(1), row.get(2), row.get(3), row.get(4), row.get(5),
+                                      row.get(6), row.get(7)))?;
+    let mut table = Table::with_headers(vec!["Id".into(), "Date".into(), "Project".into(), "Category".into(),
+                                             "Note".into(), "Amount".into(), "Invoice".into()]);
+    for row in rows {
+        let (id, date, project, category, note, amount, currency, invoice): (i64,
+                                                                             DateTime<Local>,
+                                                                             String,
+                                                                             Option<String>,
+                                                                             Option<String>,
+                                                                             i64,
+                                                                             Option<String>,
+                                                                             Option<i64>) = row?;
+        table.add_simple(vec![format!("{}", id),
+                              format!("{}", date.format("%a %d/%m/%y")),
+                              project,
+                              category.unwrap_or("".into()),
+                              note.unwrap_or("".into()),
+                              format_amounts(&[(currency, amount)]),
+                              invoice.map(|n| format!("{:04}", n)).unwrap_or("".into())]);
+    }
+    table.add_border_bottom();
+    table.print();
+    Ok(())
+}
+
+fn expense_delete(conn: &mut Connection, id: i64) -> Result<(), Error> {
+    let invoiced: i64 = conn.query_row("SELECT COUNT(*) FROM invoiced_expenses WHERE expense_id=?",
+                                       &[&id],
+                                       |row| row.get(0))?;
+    if invoiced > 0 {
+        return Err(Error::ExpenseInvoiced(id));
+    }
+    if conn.execute("DELETE FROM expenses WHERE id=?", &[&id])? == 0 {
+        return Err(Error::ExpenseNotFound(id));
+    }
+    Ok(())
+}
+
+/// The program segments recorded during the periods matching `condition`, clipped to each period
+/// and with programs renamed to the tools they are grouped under.
+fn program_segments(conn: &Connection, condition: &str, params: &[&ToSql]) -> Result<Vec<Segment>, Error> {
+    let mut stmnt = conn.prepare(&format!("SELECT program, title, program_segments.start, program_segments.end,
+                                                  estimated, timeperiods.start, timeperiods.end
+                                           FROM program_segments
+                                           JOIN timeperiods ON timeperiods.id=program_segments.timeperiod_id
+                                           WHERE {}
+                                           ORDER BY program_segments.start",
+                                          condition))?;
+    let rows = stmnt.query_map(params, |row| {
+        (Segment { program: row.get(0), title: row.get(1), start: row.get(2), end: row.get(3), estimated: row.get(4) },
+         row.get(5),
+         row.get(6))
+    })?;
+    let mut segments = vec![];
+    for row in rows {
+        let (segment, start, end): (Segment, DateTime<Local>, DateTime<Local>) = row?;
+        if let Some(segment) = segment.clip(start, end) {
+            segments.push(segment);
+        }
+    }
+    Ok(group::apply(&aliases(conn)?, segments))
+}
+
+fn print_usage(usage: &[(String, i64)]) {
+    let total_time: i64 = usage.iter().map(|&(_, time)| time).sum();
+    for &(ref program, time) in usage {
+        if time == 0 {
+            continue;
+        }
+        let pc: f32 = (time as f32) / (total_time as f32) * 100f32;
+        debug!("pc, time, total_time: {} {} {}", pc, time, total_time);
+        println!("{:>5.2}% {}", pc, program);
+    }
+}
+
+/// Prints the share of time spent in each program during the project's periods in the range. Usage
+/// recorded before it was kept per period can't be dated, so it is only counted when there is no
+/// range.
+fn print_program_usage(conn: &Connection,
+                       id: i64,
+                       week: bool,
+                       since: Option<&str>,
+                       until: Option<&str>)
+                       -> Result<(), Error> {
+    let (since_time, until_time) = parse_range(week, since, until)?;
+    let segments = program_segments(conn,
+                                    "project_id=? AND timeperiods.start > ? AND timeperiods.start < ?",
+                                    &[&id, &since_time, &until_time])?;
+    let mut usage = probe::totals(&segments);
+    if !week && since.is_none() && until.is_none() {
+        let aliases = aliases(conn)?;
+        let mut stmnt = conn.prepare("SELECT program, time FROM program_usage WHERE project_id=?")?;
         let rows = stmnt.query_map(&[&id], |row| (row.get(0), row.get(1)))?;
         for row in rows {
             let (program, time): (String, i64) = row?;
-            if time == 0 {
-                continue;
+            let program = group::tool(&aliases, &program).to_string();
+            match usage.iter().position(|&(ref p, _)| *p == program) {
+                Some(i) => usage[i].1 += time,
+                None => usage.push((program, time)),
             }
-            let pc: f32 = (time as f32) / (total_time as f32) * 100f32;
-            debug!("pc, time, total_time: {} {} {}", pc, time, total_time);
-            println!("{:>5.2}% {}", pc, program);
         }
-        println!("");
+        usage.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
     }
+    if usage.is_empty() {
+        return Ok(());
+    }
+    let subtitle_style = Style::new().underline();
+    println!("{}", subtitle_style.paint("Program usage"));
+    print_usage(&usage);
+    println!("");
+    print_categories(conn, &segments)?;
     Ok(())
 }
 
-fn project(conn: &mut Connection,
-           name: &str,
-           week: bool,
-           since: Option<&str>,
-           until: Option<&str>,
-           short: bool)
-           -> Result<(), Error>
-{
-    let (id, customer, tags, time): (i64, Option<String>, Option<String>, Option<i64>) =
-        conn.query_row("SELECT id, customer, group_concat(tag_name),
-                               (SELECT SUM(time) FROM program_usage WHERE project_id=id)
-                        FROM projects
-                        LEFT JOIN tags_projects_join ON tags_projects_join.project_id=projects.id
-                        WHERE name=?",
-                       &[&name],
-                       |row| {
-                           let id: Option<i64> = row.get(0);
-                           if let None = id {
-                               return Err(Error::ProjectNotFound(name.into()));
-                           }
-                           Ok((row.get(0), row.get(1), row.get(2), row.get(3)))
-                       })??;
-    print_project_summary(conn, id, name, customer, tags)?;
-    print_program_usage(conn, id, time)?;
-    if !short {
-        print_activity(conn, id, week, since, until)?;
+fn category_rules(conn: &Connection) -> Result<Vec<category::Rule>, Error> {
+    let mut stmnt = conn.prepare("SELECT category, program, title FROM categories ORDER BY id")?;
+    let rows = stmnt.query_map(&[], |row| (row.get(0), row.get(1), row.get(2)))?;
+    let mut rules = vec![];
+    for row in rows {
+        let (category, program, title): (String, Option<String>, Option<String>) = row?;
+        // patterns are checked when added, so this only skips rules from a newer regex syntax
+        let program = program.as_ref().map(|p| &p[..]);
+        if let Some(rule) = category::Rule::new(&category, program, title.as_ref().map(|t| &t[..])) {
+            rules.push(rule);
+        }
+    }
+    Ok(rules)
+}
+
+/// Prints the share of the tracked time in `segments` that falls in each category.
+fn print_categories(conn: &Connection, segments: &[Segment]) -> Result<(), Error> {
+    let totals: Vec<_> = category::totals(&category_rules(conn)?, segments)
+        .into_iter()
+        .filter(|&(_, time)| time > 0)
+        .collect();
+    if totals.is_empty() {
+        return Ok(());
     }
+    let total_time: i64 = probe::totals(segments).iter().map(|&(_, time)| time).sum();
+    let subtitle_style = Style::new().underline();
+    println!("{}", subtitle_style.paint("Categories"));
+    for (category, time) in totals {
+        println!("{:>5.2}% {}", (time as f32) / (total_time as f32) * 100f32, category);
+    }
+    println!("");
     Ok(())
 }
 
-fn weeks(conn: &mut Connection, name: &str) -> Result<(), Error> {
-    let (project_id, _) = find_project(conn, name)?;
-    let mut day_stmnt =
-        conn.prepare("SELECT start,
-                             SUM(CAST((julianday(end)-julianday(start))*24 AS REAL))
-                      FROM timeperiods
-                      WHERE project_id=?
-                      GROUP BY strftime('%j', start)
-                      ORDER BY strftime('%Y%W', start) DESC, start")?;
-    let rows = day_stmnt.query_map(&[&project_id], |row| (row.get(0), row.get(1)))?;
-    let mut week = 0;
-    let mut year = 0;
-    let mut start_of_week = NaiveDate::from_isoywd(1, 1, Weekday::Mon);
-    let mut table = Table::with_headers(vec!["Week".into(), "Day".into(), "Time".into()]);
-    let mut total_time = -1.0;
-    let total_separator = vec![Cell::new_left_bordered(CellType::Data("".into()), "│"),
-                               Cell::new_left_bordered(CellType::Separator, "├"),
-                               Cell::new_both_bordered(CellType::Separator, "┼", "┤")];
+fn check_pattern(title: Option<&str>) -> Result<(), Error> {
+    match title {
+        Some(title) if Pattern::new(None, Some(title)).is_none() => Err(Error::InvalidPattern(title.into())),
+        _ => Ok(()),
+    }
+}
+
+fn category_add(conn: &mut Connection,
+                category: &str,
+                program: Option<&str>,
+                title: Option<&str>)
+                -> Result<(), Error> {
+    check_pattern(title)?;
+    conn.execute("INSERT INTO categories(category, program, title) VALUES (?,?,?)",
+                 &[&category, &program, &title])?;
+    Ok(())
+}
+
+fn category_list(conn: &mut Connection) -> Result<(), Error> {
+    let mut stmnt = conn.prepare("SELECT id, category, program, title FROM categories ORDER BY id")?;
+    let rows = stmnt.query_map(&[], |row| (row.get(0), row.get(1), row.get(2), row.get(3)))?;
+    let mut table = Table::with_headers(vec!["Id".into(), "Category".into(), "Program".into(), "Title".into()]);
     for row in rows {
-        let (start, time): (DateTime<Local>, f64) = row?;
-        let (y,w,_) = start.isoweekdate();
-        let time_str = format_time(time);
-        let week_str = if w != week || y != year {
-            week = w;
-            year = y;
-            start_of_week = NaiveDate::from_isoywd(y, w, Weekday::Mon);
-            if total_time >= 0.0 {
-                table.add_row(total_separator.clone());
-                table.add_simple(vec!["".into(), "Total".into(), format_time(total_time)]);
-                table.add_full_separator();
-            }
-            total_time = 0.0;
-            format!("{}", start_of_week.format("%d/%m/%y"))
-        } else {
-            "".into()
-        };
-        total_time += time;
-        table.add_simple(vec![week_str, format!("{}", start.format("%a")), time_str]);
+        let (id, category, program, title): (i64, String, Option<String>, Option<String>) = row?;
+        table.add_simple(vec![format!("{}", id),
+                              category,
+                              program.unwrap_or("".into()),
+                              title.unwrap_or("".into())]);
     }
-    table.add_row(total_separator.clone());
-    table.add_simple(vec!["".into(), "Total".into(), format_time(total_time)]);
     table.add_border_bottom();
     table.print();
     Ok(())
 }
 
-fn short_weeks(conn: &mut Connection, name: &str) -> Result<(), Error> {
-    let (project_id, _) = find_project(conn, name)?;
-    let mut weeks_stmnt =
-        conn.prepare("SELECT start,
-                             SUM(CAST((julianday(end)-julianday(start))*24 AS REAL))
-                      FROM timeperiods
-                      WHERE project_id=?
-                      GROUP BY strftime('%W', start)
-                      ORDER BY strftime('%Y%W', start) DESC")?;
-    let rows = weeks_stmnt.query_map(&[&project_id], |row| (row.get(0), row.get(1)))?;
+fn category_remove(conn: &mut Connection, id: i64) -> Result<(), Error> {
+    if conn.execute("DELETE FROM categories WHERE id=?", &[&id])? == 0 {
+        return Err(Error::CategoryNotFound(id));
+    }
+    Ok(())
+}
+
+fn project_rules(conn: &Connection) -> Result<Vec<suggest::Rule>, Error> {
+    let mut stmnt = conn.prepare("SELECT name, kind, value FROM project_rules
+                                  JOIN projects ON projects.id=project_rules.project_id
+                                  ORDER BY project_rules.id")?;
+    let rows = stmnt.query_map(&[], |row| (row.get(0), row.get(1), row.get(2)))?;
+    let mut rules = vec![];
     for row in rows {
-        let (start, time): (DateTime<Local>, f64) = row?;
-        let (y,w,_) = start.isoweekdate();
-        let start_of_week = NaiveDate::from_isoywd(y, w, Weekday::Mon);
-        let end_of_week = NaiveDate::from_isoywd(y, w, Weekday::Sun);
-        let time_str = format_time(time);
-        println!("{}-{}\t{}", start_of_week.format("%d/%m/%y"), end_of_week.format("%d/%m/%y"), time_str);
+        let (project, kind, value): (String, String, String) = row?;
+        if let Some(rule) = suggest::Rule::new(&project, &kind, &value) {
+            rules.push(rule);
+        }
+    }
+    Ok(rules)
+}
+
+/// The urls of the remotes of the git repository in the current directory.
+fn git_remotes() -> Vec<String> {
+    match Command::new("git").args(&["remote", "-v"]).output() {
+        Ok(ref output) if output.status.success() => {
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .filter_map(|line| line.split_whitespace().nth(1))
+                .map(|url| url.into())
+                .collect()
+        }
+        _ => vec![],
+    }
+}
+
+/// The project the rules pick for the current directory.
+fn suggest_project(conn: &Connection) -> Result<String, Error> {
+    let dir = env::current_dir()?.canonicalize()?;
+    let rules = project_rules(conn)?;
+    match suggest::for_directory(&rules, &dir, &git_remotes()) {
+        Some(project) => Ok(project.into()),
+        None => Err(Error::NoProjectForDirectory),
     }
+}
+
+fn project_rule_add(conn: &mut Connection,
+                    project: &str,
+                    path: Option<&str>,
+                    remote: Option<&str>,
+                    title: Option<&str>)
+                    -> Result<(), Error> {
+    let (project_id, _) = find_project(conn, project)?;
+    let (kind, value) = match (path, remote, title) {
+        (Some(path), _, _) => ("path", fs::canonicalize(path)?.to_string_lossy().into_owned()),
+        (_, Some(remote), _) => ("remote", remote.to_string()),
+        (_, _, Some(title)) => ("title", title.to_string()),
+        _ => unreachable!(),
+    };
+    suggest::Rule::new(project, kind, &value).ok_or(Error::InvalidPattern(value.clone()))?;
+    conn.execute("INSERT INTO project_rules(project_id, kind, value) VALUES (?,?,?)",
+                 &[&project_id, &kind, &value])?;
     Ok(())
 }
 
-fn main() {
-    env_logger::init().unwrap();
+fn project_rule_list(conn: &mut Connection) -> Result<(), Error> {
+    let mut stmnt = conn.prepare("SELECT project_rules.id, name, kind, value FROM project_rules
+                                  JOIN projects ON projects.id=project_rules.project_id
+                                  ORDER BY project_rules.id")?;
+    let rows = stmnt.query_map(&[], |row| (row.get(0), row.get(1), row.get(2), row.get(3)))?;
+    let mut table = Table::with_headers(vec!["Id".into(), "Project".into(), "Kind".into(), "Value".into()]);
+    for row in rows {
+        let (id, project, kind, value): (i64, String, String, String) = row?;
+        table.add_simple(vec![format!("{}", id), project, kind, value]);
+    }
+    table.add_border_bottom();
+    table.print();
+    Ok(())
+}
 
-    let mut conn = open_connection().unwrap();
-    let matches = App::new("Timmy")
-        .version("0.1")
-        .author("Matthew Hall")
+fn project_rule_remove(conn: &mut Connection, id: i64) -> Result<(), Error> {
+    if conn.execute("DELETE FROM project_rules WHERE id=?", &[&id])? == 0 {
+        return Err(Error::ProjectRuleNotFound(id));
+    }
+    Ok(())
+}
+
+fn aliases(conn: &Connection) -> Result<Vec<group::Alias>, Error> {
+    let mut stmnt = conn.prepare("SELECT program, tool FROM program_groups")?;
+    let rows = stmnt.query_map(&[], |row| group::Alias { program: row.get(0), tool: row.get(1) })?;
+    let mut aliases = vec![];
+    for alias in rows {
+        aliases.push(alias?);
+    }
+    Ok(aliases)
+}
+
+fn group_add(conn: &mut Connection, tool: &str, programs: &[&str]) -> Result<(), Error> {
+    let tx = conn.transaction()?;
+    for program in programs {
+        tx.execute("INSERT OR REPLACE INTO program_groups(program, tool) VALUES (?,?)", &[program, &tool])?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+fn group_list(conn: &mut Connection) -> Result<(), Error> {
+    let mut stmnt = conn.prepare("SELECT tool, group_concat(program, ', ') FROM
+                                  (SELECT tool, program FROM program_groups ORDER BY tool, program)
+                                  GROUP BY tool")?;
+    let rows = stmnt.query_map(&[], |row| (row.get(0), row.get(1)))?;
+    let mut table = Table::with_headers(vec!["Tool".into(), "Programs".into()]);
+    for row in rows {
+        let (tool, programs): (String, String) = row?;
+        table.add_simple(vec![tool, programs]);
+    }
+    table.add_border_bottom();
+    table.print();
+    Ok(())
+}
+
+fn group_remove(conn: &mut Connection, programs: &[&str]) -> Result<(), Error> {
+    let tx = conn.transaction()?;
+    for program in programs {
+        if tx.execute("DELETE FROM program_groups WHERE program=?", &[program])? == 0 {
+            return Err(Error::ProgramNotGrouped(program.to_string()));
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+fn privacy_rules(conn: &Connection) -> Result<Vec<privacy::Rule>, Error> {
+    let mut stmnt = conn.prepare("SELECT action, program, title FROM privacy ORDER BY id")?;
+    let rows = stmnt.query_map(&[], |row| (row.get(0), row.get(1), row.get(2)))?;
+    let mut rules = vec![];
+    for row in rows {
+        let (action, program, title): (String, Option<String>, Option<String>) = row?;
+        let action = if action == "ignore" { privacy::Action::Ignore } else { privacy::Action::Redact };
+        if let Some(pattern) = Pattern::new(program.as_ref().map(|p| &p[..]), title.as_ref().map(|t| &t[..])) {
+            rules.push(privacy::Rule { action: action, pattern: pattern });
+        }
+    }
+    Ok(rules)
+}
+
+fn privacy_add(conn: &mut Connection,
+               action: &str,
+               program: Option<&str>,
+               title: Option<&str>)
+               -> Result<(), Error> {
+    check_pattern(title)?;
+    conn.execute("INSERT INTO privacy(action, program, title) VALUES (?,?,?)",
+                 &[&action, &program, &title])?;
+    Ok(())
+}
+
+fn privacy_list(conn: &mut Connection) -> Result<(), Error> {
+    let mut stmnt = conn.prepare("SELECT id, action, program, title FROM privacy ORDER BY id")?;
+    let rows = stmnt.query_map(&[], |row| (row.get(0), row.get(1), row.get(2), row.get(3)))?;
+    let mut table = Table::with_headers(vec!["Id".into(), "Action".into(), "Program".into(), "Title".into()]);
+    for row in rows {
+        let (id, action, program, title): (i64, String, Option<String>, Option<String>) = row?;
+        table.add_simple(vec![format!("{}", id),
+                              action,
+                              program.unwrap_or("".into()),
+                              title.unwrap_or("".into())]);
+    }
+    table.add_border_bottom();
+    table.print();
+    Ok(())
+}
+
+fn privacy_remove(conn: &mut Connection, id: i64) -> Result<(), Error> {
+    if conn.execute("DELETE FROM privacy WHERE id=?", &[&id])? == 0 {
+        return Err(Error::PrivacyRuleNotFound(id));
+    }
+    Ok(())
+}
+
+/// Deletes the program usage recorded in a range, cutting segments that cross its ends. Usage
+/// recorded before it was kept per period can't be dated, so it only goes when there is no range.
+fn purge_programs(conn: &mut Connection, since: Option<&str>, until: Option<&str>) -> Result<(), Error> {
+    let everything = since.is_none() && until.is_none();
+    let (since, until) = parse_range(false, since, until)?;
+    let mut segments = vec![];
+    {
+        let mut stmnt = conn.prepare("SELECT rowid, timeperiod_id, program, title, start, end, estimated
+                                      FROM program_segments
+                                      WHERE start < ? AND end > ?")?;
+        let rows = stmnt.query_map(&[&until, &since], |row| {
+            (row.get(0),
+             row.get(1),
+             Segment { program: row.get(2), title: row.get(3), start: row.get(4), end: row.get(5), estimated: row.get(6) })
+        })?;
+        for row in rows {
+            let row: (i64, i64, Segment) = row?;
+            segments.push(row);
+        }
+    }
+    let undated: i64 = if everything {
+        conn.query_row("SELECT COUNT(*) FROM program_usage", &[], |row| row.get(0))?
+    } else {
+        0
+    };
+    if segments.is_empty() && undated == 0 {
+        println!("There is no program usage to purge");
+        return Ok(());
+    }
+    let question = if everything {
+        "Delete all recorded program usage?".to_string()
+    } else {
+        format!("Delete the program usage recorded between {} and {}?",
+                since.format("%d/%m/%y %H:%M"),
+                until.format("%d/%m/%y %H:%M"))
+    };
+    if !confirm(&question) {
+        return Ok(());
+    }
+    let tx = conn.transaction()?;
+    for (rowid, period_id, segment) in segments {
+        tx.execute("DELETE FROM program_segments WHERE rowid=?", &[&rowid])?;
+        for &(start, end) in &[(segment.start, since), (until, segment.end)] {
+            if let Some(piece) = segment.clip(start, end) {
+                tx.execute("INSERT INTO program_segments(timeperiod_id, program, title, start, end, estimated)
+                            VALUES (?,?,?,?,?,?)",
+                           &[&period_id, &piece.program, &piece.title, &piece.start, &piece.end, &piece.estimated])?;
+            }
+        }
+    }
+    if everything {
+        tx.execute("DELETE FROM program_usage", &[])?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+fn project(conn: &mut Connection,
+           name: &str,
+           week: bool,
+           since: Option<&str>,
+           until: Option<&str>,
+           short: bool,
+           raw: bool)
+           -> Result<(), Error>
+{
+    let (id, customer, tags): (i64, Option<String>, Option<String>) =
+        conn.query_row("SELECT id, customer, group_concat(tag_name)
+                        FROM projects
+                        LEFT JOIN tags_projects_join ON tags_projects_join.project_id=projects.id
+                        WHERE name=?",
+                       &[&name],
+                       |row| {
+                           let id: Option<i64> = row.get(0);
+                           if let None = id {
+                               return Err(Error::ProjectNotFound(name.into()));
+                           }
+                           Ok((row.get(0), row.get(1), row.get(2)))
+                       })??;
+    let rounding = billing_rounding(conn, id, raw)?;
+    print_project_summary(conn, id, name, customer, tags, rounding, week, since, until)?;
+    print_program_usage(conn, id, week, since, until)?;
+    if !short {
+        print_expenses(conn, id, week, since, until)?;
+        print_activity(conn, id, week, since, until, rounding)?;
+    }
+    Ok(())
+}
+
+fn period_times(conn: &Connection,
+                project_id: i64,
+                billable: bool,
+                since: Option<DateTime<Local>>,
+                until: Option<DateTime<Local>>)
+                -> Result<Vec<(DateTime<Local>, DateTime<Local>)>, Error> {
+    let mut stmnt = conn.prepare("SELECT start, end FROM timeperiods
+                                  WHERE project_id=?1 AND billable=?2
+                                    AND (?3 IS NULL OR start > ?3) AND (?4 IS NULL OR start < ?4)
+                                  ORDER BY start")?;
+    let rows = stmnt.query_map(&[&project_id, &billable, &since, &until], |row| (row.get(0), row.get(1)))?;
+    let mut periods = vec![];
+    for row in rows {
+        periods.push(row?);
+    }
+    Ok(periods)
+}
+
+/// The billable and non-billable time spent each day on a project, or on every project, after
+/// rounding. Latest week first and then by day within each week.
+fn day_totals(conn: &Connection, project_id: Option<i64>, raw: bool) -> Result<Vec<(Date<Local>, f64, f64)>, Error> {
+    let project_ids = match project_id {
+        Some(project_id) => vec![project_id],
+        None => {
+            let mut stmnt = conn.prepare("SELECT id FROM projects")?;
+            let rows = stmnt.query_map(&[], |row| row.get(0))?;
+            let mut ids = vec![];
+            for row in rows {
+                ids.push(row?);
+            }
+            ids
+        }
+    };
+    let mut days: Vec<(Date<Local>, f64, f64)> = vec![];
+    for project_id in project_ids {
+        let rounding = billing_rounding(conn, project_id, raw)?;
+        for &billable in &[true, false] {
+            for (day, time) in rounding.day_totals(&period_times(conn, project_id, billable, None, None)?) {
+                let i = match days.iter().position(|&(d, _, _)| d == day) {
+                    Some(i) => i,
+                    None => {
+                        days.push((day, 0.0, 0.0));
+                        days.len() - 1
+                    }
+                };
+                if billable {
+                    days[i].1 += hours(time);
+                } else {
+                    days[i].2 += hours(time);
+                }
+            }
+        }
+    }
+    days.sort_by(|&(a, _, _), &(b, _, _)| {
+        let (a_year, a_week, _) = a.isoweekdate();
+        let (b_year, b_week, _) = b.isoweekdate();
+        (b_year, b_week).cmp(&(a_year, a_week)).then(a.cmp(&b))
+    });
+    Ok(days)
+}
+
+fn parse_rounding(increment: i64, per: &str, mode: &str) -> Rounding {
+    Rounding {
+        increment: Duration::seconds(increment),
+        per: if per == "day" { rounding::Per::Day } else { rounding::Per::Period },
+        mode: if mode == "up" { rounding::Mode::Up } else { rounding::Mode::Nearest },
+    }
+}
+
+/// The rounding used to bill a project: its own rule if it has one, otherwise its customer's.
+fn billing_rounding(conn: &Connection, project_id: i64, raw: bool) -> Result<Rounding, Error> {
+    if raw {
+        return Ok(Rounding::none());
+    }
+    match conn.query_row("SELECT increment, per, mode FROM rounding
+                          WHERE project_id=?1
+                             OR customer=(SELECT customer FROM projects WHERE id=?1)
+                          ORDER BY project_id IS NULL
+                          LIMIT 1",
+                         &[&project_id],
+                         |row| {
+                             let per: String = row.get(1);
+                             let mode: String = row.get(2);
+                             parse_rounding(row.get(0), &per, &mode)
+                         }) {
+        Ok(rounding) => Ok(rounding),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(Rounding::none()),
+        Err(e) => Err(Error::from(e)),
+    }
+}
+
+fn rounding_set(conn: &mut Connection,
+                project: Option<&str>,
+                customer: Option<&str>,
+                increment: &str,
+                per: &str,
+                mode: &str)
+                -> Result<(), Error> {
+    let increment = if increment == "none" {
+        Duration::zero()
+    } else {
+        chronny::parse_duration(increment).ok_or(Error::InvalidDuration(increment.into()))?
+    };
+    let project_id = match project {
+        Some(project) => Some(find_project(conn, project)?.0),
+        None => None,
+    };
+    conn.execute("INSERT OR REPLACE INTO rounding(project_id, customer, increment, per, mode)
+                  VALUES (?,?,?,?,?)",
+                 &[&project_id, &customer, &increment.num_seconds(), &per, &mode])?;
+    Ok(())
+}
+
+fn rounding_clear(conn: &mut Connection, project: Option<&str>, customer: Option<&str>) -> Result<(), Error> {
+    if let Some(project) = project {
+        let (project_id, _) = find_project(conn, project)?;
+        conn.execute("DELETE FROM rounding WHERE project_id=?", &[&project_id])?;
+    }
+    if let Some(customer) = customer {
+        conn.execute("DELETE FROM rounding WHERE customer=?", &[&customer])?;
+    }
+    Ok(())
+}
+
+fn rounding_list(conn: &mut Connection) -> Result<(), Error> {
+    let mut stmnt = conn.prepare("SELECT projects.name, rounding.customer, increment, per, mode
+                                  FROM rounding
+                                  LEFT JOIN projects ON projects.id=rounding.project_id
+                                  ORDER BY rounding.customer, projects.name")?;
+    let rows = stmnt.query_map(&[], |row| (row.get(0), row.get(1), row.get(2), row.get(3), row.get(4)))?;
+    let mut table = Table::with_headers(vec!["Project".into(), "Customer".into(), "Increment".into(),
+                                             "Per".into(), "Rounding".into()]);
+    for row in rows {
+        let (project, customer, increment, per, mode): (Option<String>, Option<String>, i64, String, String) = row?;
+        let increment = if increment == 0 {
+            "none".into()
+        } else {
+            format_time(increment as f64 / 3600.0)
+        };
+        table.add_simple(vec![project.unwrap_or("".into()), customer.unwrap_or("".into()), increment, per, mode]);
+    }
+    table.add_border_bottom();
+    table.print();
+    Ok(())
+}
+
+/// The hourly rate a project is billed at: its own rate if it has one, otherwise its customer's.
+fn billing_rate(conn: &Connection, project_id: i64) -> Result<Option<f64>, Error> {
+    match conn.query_row("SELECT rate FROM rates
+                          WHERE project_id=?1
+                             OR customer=(SELECT customer FROM projects WHERE id=?1)
+                          ORDER BY project_id IS NULL
+                          LIMIT 1",
+                         &[&project_id],
+                         |row| row.get(0)) {
+        Ok(rate) => Ok(Some(rate)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(Error::from(e)),
+    }
+}
+
+fn rate_set(conn: &mut Connection,
+            project: Option<&str>,
+            customer: Option<&str>,
+            rate: &str)
+            -> Result<(), Error> {
+    let rate = match rate.parse::<f64>() {
+        Ok(parsed) if parsed.is_finite() && parsed >= 0.0 => parsed,
+        _ => return Err(Error::InvalidRate(rate.into())),
+    };
+    let project_id = match project {
+        Some(project) => Some(find_project(conn, project)?.0),
+        None => None,
+    };
+    conn.execute("INSERT OR REPLACE INTO rates(project_id, customer, rate) VALUES (?,?,?)",
+                 &[&project_id, &customer, &rate])?;
+    Ok(())
+}
+
+fn rate_clear(conn: &mut Connection, project: Option<&str>, customer: Option<&str>) -> Result<(), Error> {
+    if let Some(project) = project {
+        let (project_id, _) = find_project(conn, project)?;
+        conn.execute("DELETE FROM rates WHERE project_id=?", &[&project_id])?;
+    }
+    if let Some(customer) = customer {
+        conn.execute("DELETE FROM rates WHERE customer=?", &[&customer])?;
+    }
+    Ok(())
+}
+
+fn rate_list(conn: &mut Connection) -> Result<(), Error> {
+    let mut stmnt = conn.prepare("SELECT projects.name, rates.customer, rate
+                                  FROM rates
+                                  LEFT JOIN projects ON projects.id=rates.project_id
+                                  ORDER BY rates.customer, projects.name")?;
+    let rows = stmnt.query_map(&[], |row| (row.get(0), row.get(1), row.get(2)))?;
+    let mut table = Table::with_headers(vec!["Project".into(), "Customer".into(), "Rate".into()]);
+    for row in rows {
+        let (project, customer, rate): (Option<String>, Option<String>, f64) = row?;
+        table.add_simple(vec![project.unwrap_or("".into()), customer.unwrap_or("".into()), format!("{:.2}", rate)]);
+    }
+    table.add_border_bottom();
+    table.print();
+    Ok(())
+}
+
+/// Bill a customer for the billable periods in a range that haven't been invoiced yet. Unless
+/// it's a dry run the invoice is numbered and the periods are recorded against it.
+fn create_invoice(conn: &mut Connection,
+                  customer: &str,
+                  week: bool,
+                  since: Option<&str>,
+                  until: Option<&str>,
+                  currency: Option<&str>,
+                  due: Option<&str>,
+                  format: invoice::Format,
+                  output: Option<&str>,
+                  dry_run: bool)
+                  -> Result<(), Error> {
+    let (since, until) = parse_range(week, since, until)?;
+    let due = if let Some(due) = due {
+        chronny::parse_datetime(due, Local::now()).ok_or(Error::InvalidDateTime(due.into()))?
+    } else {
+        Local::now() + Duration::days(30)
+    };
+    let mut lines = vec![];
+    let mut period_ids = vec![];
+    let mut expenses = vec![];
+    let mut expense_ids = vec![];
+    let mut first = until;
+    let mut last = since;
+    {
+        let mut expenses_stmnt =
+            conn.prepare("SELECT expenses.id, projects.name, date, amount_cents, currency, category, note
+                          FROM expenses
+                          JOIN projects ON projects.id=expenses.project_id
+                          WHERE customer=? AND date >= ? AND date < ?
+                            AND expenses.id NOT IN (SELECT expense_id FROM invoiced_expenses)
+                          ORDER BY date")?;
+        let rows = expenses_stmnt.query_map(&[&customer, &since, &until],
+                                            |row| (row.get(0), row.get(1), row.get(2), row.get(3), row.get(4),
+                                                   row.get(5), row.get(6)))?;
+        for row in rows {
+            let (id, project, date, amount, expense_currency, category, note): (i64,
+                                                                                String,
+                                                                                DateTime<Local>,
+                                                                                i64,
+                                                                                Option<String>,
+                                                                                Option<String>,
+                                                                                Option<String>) = row?;
+            if expense_currency.as_ref().map(|c| &c[..]) != currency {
+                // on stderr, as the invoice itself may be going to stdout
+                let _ = writeln!(io::stderr(), "Leaving out expense {} as it isn't in the invoice's currency", id);
+                continue;
+            }
+            first = if date < first { date } else { first };
+            last = if date > last { date } else { last };
+            expense_ids.push(id);
+            expenses.push(invoice::Expense {
+                date: date.date().naive_local(),
+                project: project,
+                category: category,
+                note: note,
+                amount: amount,
+            });
+        }
+    }
+    {
+        let mut projects_stmnt = conn.prepare("SELECT id, name FROM projects WHERE customer=? ORDER BY name")?;
+        let mut periods_stmnt =
+            conn.prepare("SELECT id, start, end FROM timeperiods
+                          WHERE project_id=? AND billable AND heartbeat IS NULL
+                            AND start > ? AND start < ?
+                            AND id NOT IN (SELECT timeperiod_id FROM invoiced_periods)
+                          ORDER BY start")?;
+        let projects = projects_stmnt.query_map(&[&customer], |row| (row.get(0), row.get(1)))?;
+        for project in projects {
+            let (project_id, project): (i64, String) = project?;
+            let mut times = vec![];
+            for period in periods_stmnt.query_map(&[&project_id, &since, &until],
+                                                  |row| (row.get(0), row.get(1), row.get(2)))? {
+                let (id, start, end): (i64, DateTime<Local>, DateTime<Local>) = period?;
+                first = if start < first { start } else { first };
+                last = if end > last { end } else { last };
+                period_ids.push(id);
+                times.push((start, end));
+            }
+            if times.is_empty() {
+                continue;
+            }
+            let rate = billing_rate(conn, project_id)?.ok_or(Error::NoRate(project.clone()))?;
+            let rounding = billing_rounding(conn, project_id, false)?;
+            lines.push(invoice::Line {
+                project: project,
+                hours: hours(rounding.total(&times)),
+                rate: invoice::cents(rate),
+            });
+        }
+    }
+    if lines.is_empty() && expenses.is_empty() {
+        return Err(Error::NothingToInvoice(customer.into()));
+    }
+    // without a --since the range starts at year 1, so show the time actually covered instead
+    let since = if since < first { first } else { since };
+    let until = if until > last { last } else { until };
+    let mut invoice = Invoice {
+        number: 0,
+        provisional: dry_run,
+        customer: customer.into(),
+        date: Local::today().naive_local(),
+        since: since.date().naive_local(),
+        until: until.date().naive_local(),
+        currency: currency.map(|c| c.into()),
+        lines: lines,
+        expenses: expenses,
+    };
+    let tx = conn.transaction()?;
+    if dry_run {
+        invoice.number = tx.query_row("SELECT IFNULL(MAX(id), 0) + 1 FROM invoices", &[], |row| row.get(0))?;
+    } else {
+        tx.execute("INSERT INTO invoices(customer, date, since, until, currency, total, total_cents, due)
+                    VALUES (?,?,?,?,?,?,?,?)",
+                   &[&customer, &Local::now(), &since, &until, &currency, &(invoice.total() as f64 / 100.0),
+                     &invoice.total(), &due])?;
+        invoice.number = tx.last_insert_rowid();
+        for id in &period_ids {
+            tx.execute("INSERT INTO invoiced_periods(invoice_id, timeperiod_id) VALUES (?,?)",
+                       &[&invoice.number, id])?;
+            tx.execute("UPDATE timeperiods SET locked=1 WHERE id=?", &[id])?;
+        }
+        for id in &expense_ids {
+            tx.execute("INSERT INTO invoiced_expenses(invoice_id, expense_id) VALUES (?,?)",
+                       &[&invoice.number, id])?;
+        }
+    }
+    let rendered = invoice.render(format);
+    // the file only takes its name once the invoice is recorded, so a failed commit can't leave
+    // behind an invoice that was never recorded
+    let temp = output.map(|output| format!("{}.{}.tmp", output, process::id()));
+    if let Some(ref temp) = temp {
+        fs::File::create(temp)?.write_all(rendered.as_bytes())?;
+    }
+    if let Err(e) = tx.commit() {
+        if let Some(ref temp) = temp {
+            let _ = fs::remove_file(temp);
+        }
+        return Err(Error::from(e));
+    }
+    match (output, temp) {
+        (Some(output), Some(temp)) => {
+            fs::rename(temp, output)?;
+            println!("Wrote {} to {}", invoice.title(), output);
+        }
+        _ => print!("{}", rendered),
+    }
+    Ok(())
+}
+
+fn invoice_status(conn: &mut Connection, number: i64, status: &str) -> Result<(), Error> {
+    if conn.execute("UPDATE invoices SET status=? WHERE id=?", &[&status, &number])? == 0 {
+        return Err(Error::InvoiceNotFound(number));
+    }
+    Ok(())
+}
+
+fn invoices(conn: &mut Connection, unpaid: bool) -> Result<(), Error> {
+    // a sent invoice becomes overdue once its due date has passed
+    let mut stmnt = conn.prepare("SELECT id, customer, date, due, total_cents, currency,
+                                         CASE WHEN status='sent' AND due < ?1 THEN 'overdue'
+                                              ELSE status END
+                                  FROM invoices
+                                  WHERE NOT ?2 OR status != 'paid'
+                                  ORDER BY id")?;
+    let rows = stmnt.query_map(&[&Local::now(), &unpaid],
+                               |row| (row.get(0), row.get(1), row.get(2), row.get(3), row.get(4), row.get(5),
+                                      row.get(6)))?;
+    let mut table = Table::with_headers(vec!["Number".into(), "Customer".into(), "Date".into(),
+                                             "Due".into(), "Total".into(), "Status".into()]);
+    // customer -> (outstanding, overdue, currency)
+    let mut outstanding: Vec<(String, i64, i64, Option<String>)> = vec![];
+    for row in rows {
+        let (id, customer, date, due, total, currency, status): (i64,
+                                                                 String,
+                                                                 DateTime<Local>,
+                                                                 Option<DateTime<Local>>,
+                                                                 i64,
+                                                                 Option<String>,
+                                                                 String) = row?;
+        let amount = match currency {
+            Some(ref currency) => format!("{} {}", invoice::format_cents(total), currency),
+            None => invoice::format_cents(total),
+        };
+        table.add_simple(vec![format!("{:04}", id),
+                              customer.clone(),
+                              format!("{}", date.format("%d/%m/%y")),
+                              due.map(|due| format!("{}", due.format("%d/%m/%y"))).unwrap_or("".into()),
+                              amount,
+                              status.clone()]);
+        if status == "paid" {
+            continue;
+        }
+        let overdue = if status == "overdue" { total } else { 0 };
+        match outstanding.iter().position(|&(ref c, _, _, ref cur)| *c == customer && *cur == currency) {
+            Some(i) => {
+                outstanding[i].1 += total;
+                outstanding[i].2 += overdue;
+            }
+            None => outstanding.push((customer, total, overdue, currency)),
+        }
+    }
+    table.add_border_bottom();
+    table.print();
+    if unpaid {
+        let mut table = Table::with_headers(vec!["Customer".into(), "Outstanding".into(), "Overdue".into()]);
+        for (customer, total, overdue, currency) in outstanding {
+            let currency = currency.map(|c| format!(" {}", c)).unwrap_or("".into());
+            table.add_simple(vec![customer,
+                                  format!("{}{}", invoice::format_cents(total), currency),
+                                  format!("{}{}", invoice::format_cents(overdue), currency)]);
+        }
+        table.add_border_bottom();
+        table.print();
+    }
+    Ok(())
+}
+
+/// Like format_time but for time that can be negative, eg. how far under a target you are.
+fn format_delta(time: f64) -> String {
+    if time.abs() < 1.0 / 60.0 {
+        "0mins".into()
+    } else if time < 0.0 {
+        format!("-{}", format_time(-time))
+    } else {
+        format!("+{}", format_time(time))
+    }
+}
+
+/// The weekly target for a project, or the contracted hours when there's no project, and when it
+/// was set.
+fn weekly_target(conn: &Connection,
+                 project_id: Option<i64>)
+                 -> Result<Option<(f64, Option<DateTime<Local>>)>, Error> {
+    match conn.query_row("SELECT hours, since FROM targets WHERE project_id IS ?",
+                         &[&project_id],
+                         |row| (row.get(0), row.get(1))) {
+        Ok(target) => Ok(Some(target)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(Error::from(e)),
+    }
+}
+
+fn target_set(conn: &mut Connection, project: Option<&str>, hours: &str) -> Result<(), Error> {
+    let hours = match hours.parse::<f64>() {
+        Ok(parsed) if parsed.is_finite() && parsed >= 0.0 => parsed,
+        _ => return Err(Error::InvalidHours(hours.into())),
+    };
+    let project_id = match project {
+        Some(project) => Some(find_project(conn, project)?.0),
+        None => None,
+    };
+    // UNIQUE doesn't stop there being more than one NULL, so replace the old target by hand
+    let tx = conn.transaction()?;
+    tx.execute("DELETE FROM targets WHERE project_id IS ?", &[&project_id])?;
+    tx.execute("INSERT INTO targets(project_id, hours, since) VALUES (?,?,?)",
+               &[&project_id, &hours, &Local::now()])?;
+    tx.commit()?;
+    Ok(())
+}
+
+fn target_clear(conn: &mut Connection, project: Option<&str>) -> Result<(), Error> {
+    let project_id = match project {
+        Some(project) => Some(find_project(conn, project)?.0),
+        None => None,
+    };
+    conn.execute("DELETE FROM targets WHERE project_id IS ?", &[&project_id])?;
+    Ok(())
+}
+
+fn target_list(conn: &mut Connection) -> Result<(), Error> {
+    let mut stmnt = conn.prepare("SELECT projects.name, hours
+                                  FROM targets
+                                  LEFT JOIN projects ON projects.id=targets.project_id
+                                  ORDER BY projects.name IS NOT NULL, projects.name")?;
+    let rows = stmnt.query_map(&[], |row| (row.get(0), row.get(1)))?;
+    let mut table = Table::with_headers(vec!["Project".into(), "Hours per week".into()]);
+    for row in rows {
+        let (project, hours): (Option<String>, f64) = row?;
+        table.add_simple(vec![project.unwrap_or("(contracted hours)".into()), format_time(hours)]);
+    }
+    table.add_border_bottom();
+    table.print();
+    Ok(())
+}
+
+/// The days between `from` and `until`, inclusive, that are taken off.
+fn days_off(conn: &Connection, from: NaiveDate, until: NaiveDate) -> Result<Vec<NaiveDate>, Error> {
+    let mut stmnt = conn.prepare("SELECT start, end FROM timeoff WHERE start <= ? AND end >= ?")?;
+    let rows = stmnt.query_map(&[&until, &from], |row| (row.get(0), row.get(1)))?;
+    let mut days = vec![];
+    for row in rows {
+        let (start, end): (NaiveDate, NaiveDate) = row?;
+        let mut day = if start > from { start } else { from };
+        let end = if end < until { end } else { until };
+        while day <= end {
+            if !days.contains(&day) {
+                days.push(day);
+            }
+            day = day.succ();
+        }
+    }
+    days.sort();
+    Ok(days)
+}
+
+fn off_add(conn: &mut Connection,
+           start: &str,
+           until: Option<&str>,
+           typ: &str,
+           note: Option<&str>)
+           -> Result<(), Error> {
+    let start_time = chronny::parse_datetime(start, Local::now()).ok_or(Error::InvalidDateTime(start.into()))?;
+    let start = start_time.date().naive_local();
+    let end = if let Some(until) = until {
+        let end = chronny::parse_datetime(until, start_time).ok_or(Error::InvalidDateTime(until.into()))?;
+        let mut end = end.date().naive_local();
+        // "24/12" until "2/1" means the 2nd of January after, not the one before
+        if end < start {
+            end = NaiveDate::from_ymd_opt(end.year() + 1, end.month(), end.day())
+                      .ok_or(Error::InvalidDateTime(until.into()))?;
+        }
+        if end < start {
+            return Err(Error::InvalidDateTime(until.into()));
+        }
+        end
+    } else {
+        start
+    };
+    conn.execute("INSERT INTO timeoff(start, end, type, note) VALUES (?,?,?,?)",
+                 &[&start, &end, &typ, &note])?;
+    println!("Off from {} until {}", start.format("%a %d/%m/%y"), end.format("%a %d/%m/%y"));
+    Ok(())
+}
+
+fn off_list(conn: &mut Connection) -> Result<(), Error> {
+    let mut stmnt = conn.prepare("SELECT id, start, end, type, note FROM timeoff ORDER BY start DESC")?;
+    let rows = stmnt.query_map(&[], |row| (row.get(0), row.get(1), row.get(2), row.get(3), row.get(4)))?;
+    let mut table = Table::with_headers(vec!["Id".into(), "From".into(), "Until".into(), "Type".into(),
+                                             "Note".into()]);
+    for row in rows {
+        let (id, start, end, typ, note): (i64, NaiveDate, NaiveDate, String, Option<String>) = row?;
+        table.add_simple(vec![format!("{}", id),
+                              format!("{}", start.format("%a %d/%m/%y")),
+                              format!("{}", end.format("%a %d/%m/%y")),
+                              typ,
+                              note.unwrap_or("".into())]);
+    }
+    table.add_border_bottom();
+    table.print();
+    Ok(())
+}
+
+fn off_remove(conn: &mut Connection, id: i64) -> Result<(), Error> {
+    if conn.execute("DELETE FROM timeoff WHERE id=?", &[&id])? == 0 {
+        return Err(Error::TimeOffNotFound(id));
+    }
+    Ok(())
+}
+
+struct Week {
+    start: NaiveDate,
+    days: Vec<(Date<Local>, f64, f64)>,
+    /// Working days taken off this week
+    days_off: usize,
+    target: Option<f64>,
+    /// The overtime built up by the end of this week, when there is a target
+    balance: f64,
+}
+
+impl Week {
+    fn new(start: NaiveDate) -> Week {
+        Week { start: start, days: vec![], days_off: 0, target: None, balance: 0.0 }
+    }
+
+    fn billable(&self) -> f64 {
+        self.days.iter().map(|&(_, billable, _)| billable).sum()
+    }
+
+    fn non_billable(&self) -> f64 {
+        self.days.iter().map(|&(_, _, non_billable)| non_billable).sum()
+    }
+
+    fn total(&self) -> f64 {
+        self.billable() + self.non_billable()
+    }
+}
+
+/// The time spent each week on a project, or on everything, latest week first. With a target,
+/// weeks where nothing was tracked since it was set are filled in up to this week since they still
+/// count against the target. Each week is only charged the share of the target for its working
+/// hours after the target was set, not taken off and, for this week, already gone by.
+fn tracked_weeks(conn: &Connection, project_id: Option<i64>, raw: bool) -> Result<Vec<Week>, Error> {
+    let mut weeks: Vec<Week> = vec![];
+    for (day, billable, non_billable) in day_totals(conn, project_id, raw)? {
+        let (y,w,_) = day.isoweekdate();
+        let start = NaiveDate::from_isoywd(y, w, Weekday::Mon);
+        if weeks.last().map(|week| week.start) != Some(start) {
+            weeks.push(Week::new(start));
+        }
+        weeks.last_mut().unwrap().days.push((day, billable, non_billable));
+    }
+    let (target, since) = match weekly_target(conn, project_id)? {
+        Some(target) => target,
+        None => return Ok(weeks),
+    };
+    let hours = load_working_hours(conn)?;
+    let now = Local::now();
+    let week_of = |day: NaiveDate| day - Duration::days(day.weekday().num_days_from_monday() as i64);
+    let this_week = week_of(now.date().naive_local());
+    let mut first = weeks.last().map(|week| week.start).unwrap_or(this_week);
+    if let Some(since) = since {
+        let since = week_of(since.date().naive_local());
+        if since > first {
+            first = since;
+        }
+    }
+    let mut start = weeks.first().map(|week| week.start).unwrap_or(this_week);
+    if this_week > start {
+        start = this_week;
+    }
+    let mut tracked = weeks.into_iter().peekable();
+    let mut weeks = vec![];
+    while start >= first {
+        if tracked.peek().map(|week| week.start) == Some(start) {
+            weeks.push(tracked.next().unwrap());
+        } else {
+            weeks.push(Week::new(start));
+        }
+        start = start - Duration::days(7);
+    }
+    weeks.extend(tracked);
+    let mut balance = 0.0;
+    for week in weeks.iter_mut().rev() {
+        let week_start = Local.from_local_date(&week.start).unwrap().and_hms(0, 0, 0);
+        let week_end = week_start + Duration::days(7);
+        let from = match since {
+            Some(since) if since > week_start => since,
+            _ => week_start,
+        };
+        if from >= week_end {
+            continue;
+        }
+        let until = if now < week_end { now } else { week_end };
+        let off: Vec<NaiveDate> = days_off(conn, week.start, week.start + Duration::days(6))?
+            .into_iter()
+            .filter(|day| hours.iter().any(|hours| hours.days.contains(&day.weekday())))
+            .collect();
+        week.days_off = off.len();
+        let full = chronny::working_time(&hours, week_start, week_end, &[]).num_seconds();
+        let charged = if from < until {
+            chronny::working_time(&hours, from, until, &off).num_seconds()
+        } else {
+            0
+        };
+        let week_target = if full > 0 { target * charged as f64 / full as f64 } else { 0.0 };
+        balance += week.total() - week_target;
+        week.target = Some(week_target);
+        week.balance = balance;
+    }
+    Ok(weeks)
+}
+
+fn weeks(conn: &mut Connection, name: Option<&str>, raw: bool) -> Result<(), Error> {
+    let project_id = match name {
+        Some(name) => Some(find_project(conn, name)?.0),
+        None => None,
+    };
+    let mut table = Table::with_headers(vec!["Week".into(), "Day".into(), "Billable".into(),
+                                             "Non-billable".into(), "Total".into()]);
+    let total_separator = vec![Cell::new_left_bordered(CellType::Data("".into()), "│"),
+                               Cell::new_left_bordered(CellType::Separator, "├"),
+                               Cell::new_left_bordered(CellType::Separator, "┼"),
+                               Cell::new_left_bordered(CellType::Separator, "┼"),
+                               Cell::new_both_bordered(CellType::Separator, "┼", "┤")];
+    let weeks = tracked_weeks(conn, project_id, raw)?;
+    for (i, week) in weeks.iter().enumerate() {
+        if i > 0 {
+            table.add_full_separator();
+        }
+        let week_str = format!("{}", week.start.format("%d/%m/%y"));
+        if week.days.is_empty() {
+            table.add_simple(vec![week_str.clone(), "".into(), "".into(), "".into(), "".into()]);
+        }
+        for (j, &(day, billable, non_billable)) in week.days.iter().enumerate() {
+            table.add_simple(vec![if j == 0 { week_str.clone() } else { "".into() },
+                                  format!("{}", day.format("%a")), format_time(billable),
+                                  format_time(non_billable), format_time(billable + non_billable)]);
+        }
+        table.add_row(total_separator.clone());
+        table.add_simple(vec!["".into(), "Total".into(), format_time(week.billable()),
+                              format_time(week.non_billable()), format_time(week.total())]);
+        if let Some(target) = week.target {
+            if week.days_off > 0 {
+                table.add_simple(vec!["".into(), "Days off".into(), "".into(), "".into(),
+                                      format!("{}", week.days_off)]);
+            }
+            table.add_simple(vec!["".into(), "Target".into(), "".into(), "".into(), format_time(target)]);
+            table.add_simple(vec!["".into(), "Delta".into(), "".into(), "".into(),
+                                  format_delta(week.total() - target)]);
+            table.add_simple(vec!["".into(), "Overtime".into(), "".into(), "".into(),
+                                  format_delta(week.balance)]);
+        }
+    }
+    if weeks.is_empty() {
+        table.add_row(total_separator.clone());
+        table.add_simple(vec!["".into(), "Total".into(), format_time(0.0), format_time(0.0), format_time(0.0)]);
+    }
+    table.add_border_bottom();
+    table.print();
+    Ok(())
+}
+
+fn short_weeks(conn: &mut Connection, name: Option<&str>, raw: bool) -> Result<(), Error> {
+    let project_id = match name {
+        Some(name) => Some(find_project(conn, name)?.0),
+        None => None,
+    };
+    for week in tracked_weeks(conn, project_id, raw)? {
+        let end_of_week = week.start + Duration::days(6);
+        let target_str = if let Some(target) = week.target {
+            format!("\t{}\t{}\t{}", format_time(target), format_delta(week.total() - target), format_delta(week.balance))
+        } else {
+            "".into()
+        };
+        println!("{}-{}\t{}\t{}\t{}{}",
+                 week.start.format("%d/%m/%y"),
+                 end_of_week.format("%d/%m/%y"),
+                 format_time(week.billable()),
+                 format_time(week.non_billable()),
+                 format_time(week.total()),
+                 target_str);
+    }
+    Ok(())
+}
+
+fn customer(conn: &mut Connection,
+            name: &str,
+            week: bool,
+            since: Option<&str>,
+            until: Option<&str>,
+            raw: bool)
+            -> Result<(), Error> {
+    let (since, until) = parse_range(week, since, until)?;
+    let mut stmnt = conn.prepare("SELECT id, name FROM projects WHERE customer=? ORDER BY name")?;
+    let rows = stmnt.query_map(&[&name], |row| (row.get(0), row.get(1)))?;
+    let mut table = Table::with_headers(vec!["Project".into(), "Billable".into(),
+                                             "Non-billable".into(), "Total".into(), "Expenses".into()]);
+    let mut total_billable = 0.0;
+    let mut total_non_billable = 0.0;
+    let mut total_expenses = vec![];
+    for row in rows {
+        let (project_id, project): (i64, String) = row?;
+        let rounding = billing_rounding(conn, project_id, raw)?;
+        let billable = hours(rounding.total(&period_times(conn, project_id, true, Some(since), Some(until))?));
+        let non_billable = hours(rounding.total(&period_times(conn, project_id, false, Some(since), Some(until))?));
+        let expenses = expense_totals(conn, project_id, Some(since), Some(until))?;
+        total_billable += billable;
+        total_non_billable += non_billable;
+        add_amounts(&mut total_expenses, &expenses);
+        table.add_simple(vec![project, format_time(billable), format_time(non_billable),
+                              format_time(billable + non_billable), format_amounts(&expenses)]);
+    }
+    table.add_full_separator();
+    table.add_simple(vec!["Total".into(), format_time(total_billable), format_time(total_non_billable),
+                          format_time(total_billable + total_non_billable), format_amounts(&total_expenses)]);
+    table.add_border_bottom();
+    table.print();
+    Ok(())
+}
+
+fn check_unlocked(conn: &Connection, id: i64) -> Result<(), Error> {
+    let locked: bool = conn.query_row("SELECT locked FROM timeperiods WHERE id=?", &[&id], |row| row.get(0))?;
+    if locked {
+        return Err(Error::PeriodLocked(id));
+    }
+    Ok(())
+}
+
+/// Finds a period that is about to be changed, which isn't allowed once it has been invoiced.
+fn find_period(conn: &Connection,
+               id: i64)
+               -> Result<(i64, DateTime<Local>, DateTime<Local>, Option<String>), Error> {
+    match conn.query_row("SELECT project_id, start, end, description, locked FROM timeperiods WHERE id=?",
+                         &[&id],
+                         |row| (row.get(0), row.get(1), row.get(2), row.get(3), row.get(4))) {
+        Ok((_, _, _, _, true)) => Err(Error::PeriodLocked(id)),
+        Ok((project_id, start, end, description, false)) => Ok((project_id, start, end, description)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Err(Error::PeriodNotFound(id)),
+        Err(e) => Err(Error::from(e)),
+    }
+}
+
+/// Prints how often focus switched program during a period and how long it stayed put.
+fn print_focus(conn: &Connection, segments: &[Segment]) -> Result<(), Error> {
+    let metrics = focus::metrics(segments, &focus_programs(conn)?);
+    let subtitle_style = Style::new().underline();
+    println!("{}", subtitle_style.paint("Focus"));
+    println!("Switches: {}", metrics.switches);
+    if let Some(ref longest) = metrics.longest {
+        println!("Longest stretch: {}-{} {} ({})",
+                 longest.start.format("%H:%M"),
+                 longest.end.format("%H:%M"),
+                 longest.program,
+                 format_time(hours(longest.duration())));
+    }
+    if metrics.focus > Duration::zero() {
+        println!("In focus programs: {:.2}%", metrics.focus_share());
+    }
+    println!("");
+    Ok(())
+}
+
+fn print_timeline(segments: &[Segment]) {
+    let subtitle_style = Style::new().underline();
+    println!("{}", subtitle_style.paint("Timeline"));
+    for stretch in focus::timeline(segments) {
+        println!("{}-{} {}", stretch.start.format("%H:%M"), stretch.end.format("%H:%M"), stretch.program);
+    }
+    println!("");
+}
+
+fn period_show(conn: &Connection, id: i64, timeline: bool) -> Result<(), Error> {
+    let (project, start, end, description, billable): (String,
+                                                        DateTime<Local>,
+                                                        DateTime<Local>,
+                                                        Option<String>,
+                                                        bool) =
+        match conn.query_row("SELECT name, start, end, description, timeperiods.billable
+                              FROM timeperiods JOIN projects ON projects.id=timeperiods.project_id
+                              WHERE timeperiods.id=?",
+                             &[&id],
+                             |row| (row.get(0), row.get(1), row.get(2), row.get(3), row.get(4))) {
+            Ok(period) => period,
+            Err(rusqlite::Error::QueryReturnedNoRows) => return Err(Error::PeriodNotFound(id)),
+            Err(e) => return Err(Error::from(e)),
+        };
+    let title_style = Style::new().underline().bold();
+    println!("{}", title_style.paint(format!("Period {}: {}", id, project)));
+    println!("{} {}-{} {}{}{}",
+             start.format("%a %d %B %Y"),
+             start.format("%H:%M"),
+             end.format("%H:%M"),
+             format_time(hours(end - start)),
+             if billable { "" } else { " (non-billable)" },
+             description.map(|d| format!(": {}", d)).unwrap_or_default());
+    let mut stmnt = conn.prepare("SELECT summary FROM commits WHERE timeperiod_id=?")?;
+    let commits = stmnt.query_map(&[&id], |row| row.get(0))?;
+    for commit in commits {
+        let msg: String = commit?;
+        println!("    * {}", msg);
+    }
+    let segments = program_segments(conn, "timeperiods.id=?", &[&id])?;
+    let usage = probe::totals(&segments);
+    if !usage.is_empty() {
+        let subtitle_style = Style::new().underline();
+        println!("");
+        println!("{}", subtitle_style.paint("Program usage"));
+        print_usage(&usage);
+        if segments.iter().any(|segment| segment.estimated) {
+            println!("Some of this was estimated from CPU time, so it is left out of focus and the timeline");
+        }
+        println!("");
+        print_categories(conn, &segments)?;
+        if segments.iter().any(|segment| !segment.estimated) {
+            print_focus(conn, &segments)?;
+            if timeline {
+                print_timeline(&segments);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn period_lock(conn: &mut Connection, id: i64, locked: bool) -> Result<(), Error> {
+    if conn.execute("UPDATE timeperiods SET locked=? WHERE id=?", &[&locked, &id])? == 0 {
+        return Err(Error::PeriodNotFound(id));
+    }
+    Ok(())
+}
+
+fn periods(conn: &mut Connection,
+           name: Option<&str>,
+           week: bool,
+           since: Option<&str>,
+           until: Option<&str>)
+           -> Result<(), Error> {
+    let (since, until) = parse_range(week, since, until)?;
+    let project_id = match name {
+        Some(name) => Some(find_project(conn, name)?.0),
+        None => None,
+    };
+    let mut stmnt = conn.prepare("SELECT timeperiods.id, projects.name, start, end, description,
+                                         CAST((julianday(end)-julianday(start))*24 AS REAL),
+                                         timeperiods.billable
+                                  FROM timeperiods
+                                  JOIN projects ON projects.id=timeperiods.project_id
+                                  WHERE (?1 IS NULL OR project_id=?1) AND start > ?2 AND start < ?3
+                                  ORDER BY start DESC")?;
+    let rows = stmnt.query_map(&[&project_id, &since, &until],
+                               |row| (row.get(0), row.get(1), row.get(2), row.get(3), row.get(4), row.get(5),
+                                      row.get(6)))?;
+    let mut table = Table::with_headers(vec!["Id".into(), "Project".into(), "Start".into(),
+                                             "End".into(), "Time".into(), "Billable".into(),
+                                             "Description".into()]);
+    for row in rows {
+        let (id, project, start, end, description, time, billable): (i64,
+                                                                     String,
+                                                                     DateTime<Local>,
+                                                                     DateTime<Local>,
+                                                                     Option<String>,
+                                                                     f64,
+                                                                     bool) = row?;
+        table.add_simple(vec![format!("{}", id),
+                              project,
+                              format!("{}", start.format("%a %d/%m/%y %H:%M")),
+                              format!("{}", end.format("%H:%M")),
+                              format_time(time),
+                              if billable { "yes".into() } else { "no".into() },
+                              description.unwrap_or("".into())]);
+    }
+    table.add_border_bottom();
+    table.print();
+    Ok(())
+}
+
+fn period_edit(conn: &mut Connection,
+               id: i64,
+               start: Option<&str>,
+               end: Option<&str>,
+               description: Option<&str>,
+               billable: Option<bool>,
+               policy: OverlapPolicy)
+               -> Result<(), Error> {
+    let (_, old_start, old_end, _) = find_period(conn, id)?;
+    let start = if let Some(start) = start {
+        chronny::parse_datetime(start, old_start).ok_or(Error::InvalidDateTime(start.into()))?
+    } else {
+        old_start
+    };
+    let end = if let Some(end) = end {
+        parse_end(end, start)?
+    } else {
+        old_end
+    };
+    check_times(start, end)?;
+    let tx = conn.transaction()?;
+    if let Some(description) = description {
+        tx.execute("UPDATE timeperiods SET description=? WHERE id=?", &[&description, &id])?;
+    }
+    if let Some(billable) = billable {
+        tx.execute("UPDATE timeperiods SET billable=? WHERE id=?", &[&billable, &id])?;
+    }
+    let pieces = resolve_overlaps(&tx, start, end, Some(id), policy)?;
+    reshape_period(&tx, id, &pieces)?;
+    tx.commit()?;
+    println!("Period {} is now {}-{}", id, start.format("%d/%m/%y %H:%M"), end.format("%d/%m/%y %H:%M"));
+    Ok(())
+}
+
+fn check_times(start: DateTime<Local>, end: DateTime<Local>) -> Result<(), Error> {
+    if end <= start {
+        return Err(Error::EndBeforeStart(start, end));
+    }
+    Ok(())
+}
+
+fn period_move(conn: &mut Connection, id: i64, project: &str) -> Result<(), Error> {
+    let (proj_id, active) = find_project(conn, project)?;
+    if !active {
+        return Err(Error::InactiveProject(project.into()));
+    }
+    find_period(conn, id)?;
+    let tx = conn.transaction()?;
+    tx.execute("UPDATE timeperiods SET project_id=? WHERE id=?", &[&proj_id, &id])?;
+    tx.execute("UPDATE commits SET project_id=? WHERE timeperiod_id=?", &[&proj_id, &id])?;
+    tx.execute("UPDATE pomodoros SET project_id=? WHERE timeperiod_id=?", &[&proj_id, &id])?;
+    tx.commit()?;
+    Ok(())
+}
+
+fn period_split(conn: &mut Connection, id: i64, at: &str) -> Result<(), Error> {
+    let (_, start, end, _) = find_period(conn, id)?;
+    let split = chronny::parse_datetime(at, start).ok_or(Error::InvalidDateTime(at.into()))?;
+    if split <= start || split >= end {
+        return Err(Error::SplitOutsidePeriod(at.into()));
+    }
+    let tx = conn.transaction()?;
+    reshape_period(&tx, id, &[(start, split), (split, end)])?;
+    tx.commit()?;
+    Ok(())
+}
+
+fn period_merge(conn: &mut Connection,
+                id: i64,
+                other: i64,
+                policy: OverlapPolicy)
+                -> Result<(), Error> {
+    if id == other {
+        return Err(Error::MergeWithItself(id));
+    }
+    let (proj_id, start, end, description) = find_period(conn, id)?;
+    let (other_proj_id, other_start, other_end, other_description) = find_period(conn, other)?;
+    if proj_id != other_proj_id {
+        return Err(Error::DifferentProjects);
+    }
+    let start = if other_start < start { other_start } else { start };
+    let end = if other_end > end { other_end } else { end };
+    let tx = conn.transaction()?;
+    if description.is_none() {
+        tx.execute("UPDATE timeperiods SET description=? WHERE id=?", &[&other_description, &id])?;
+    }
+    tx.execute("UPDATE commits SET timeperiod_id=? WHERE timeperiod_id=?", &[&id, &other])?;
+    tx.execute("UPDATE program_segments SET timeperiod_id=? WHERE timeperiod_id=?", &[&id, &other])?;
+    tx.execute("UPDATE pomodoros SET timeperiod_id=? WHERE timeperiod_id=?", &[&id, &other])?;
+    tx.execute("DELETE FROM timeperiods WHERE id=?", &[&other])?;
+    // anything in the gap between the two periods is now covered, so check it again
+    let pieces = resolve_overlaps(&tx, start, end, Some(id), policy)?;
+    reshape_period(&tx, id, &pieces)?;
+    tx.commit()?;
+    Ok(())
+}
+
+fn period_delete(conn: &mut Connection, id: i64) -> Result<(), Error> {
+    let (_, start, end, _) = find_period(conn, id)?;
+    let tx = conn.transaction()?;
+    tx.execute("DELETE FROM commits WHERE timeperiod_id=?", &[&id])?;
+    tx.execute("DELETE FROM program_segments WHERE timeperiod_id=?", &[&id])?;
+    tx.execute("DELETE FROM pomodoros WHERE timeperiod_id=?", &[&id])?;
+    tx.execute("DELETE FROM timeperiods WHERE id=?", &[&id])?;
+    tx.commit()?;
+    println!("Deleted period {} {}-{}", id, start.format("%d/%m/%y %H:%M"), end.format("%H:%M"));
+    Ok(())
+}
+
+const DEFAULT_WORKING_HOURS: &'static str = "mon-fri 09:00-17:30";
+
+fn setting(conn: &Connection, key: &str) -> Result<Option<String>, Error> {
+    match conn.query_row("SELECT value FROM settings WHERE key=?", &[&key], |row| row.get(0)) {
+        Ok(value) => Ok(Some(value)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(Error::from(e)),
+    }
+}
+
+fn set_setting(conn: &Connection, key: &str, value: &str) -> Result<(), Error> {
+    conn.execute("INSERT OR REPLACE INTO settings(key, value) VALUES (?,?)", &[&key, &value])?;
+    Ok(())
+}
+
+/// The programs counted as focused work, eg. editors and terminals.
+fn focus_programs(conn: &Connection) -> Result<Vec<String>, Error> {
+    Ok(setting(conn, "focus_programs")?
+        .map(|programs| programs.split(',').map(|program| program.into()).collect())
+        .unwrap_or_default())
+}
+
+fn set_focus_programs(conn: &mut Connection, programs: Option<Vec<&str>>, clear: bool) -> Result<(), Error> {
+    if clear {
+        conn.execute("DELETE FROM settings WHERE key='focus_programs'", &[])?;
+    } else if let Some(programs) = programs {
+        set_setting(conn, "focus_programs", &programs.join(","))?;
+    } else {
+        for program in focus_programs(conn)? {
+            println!("{}", program);
+        }
+    }
+    Ok(())
+}
+
+/// How many milliseconds the program tracker waits between looking, if it has been changed.
+fn sample_interval(conn: &Connection) -> Result<Option<u64>, Error> {
+    Ok(setting(conn, "sample_interval")?.and_then(|interval| interval.parse().ok()))
+}
+
+/// How long a window has to have focus for to be recorded rather than folded into the one before.
+fn min_dwell(conn: &Connection) -> Result<Duration, Error> {
+    Ok(setting(conn, "min_dwell")?.and_then(|dwell| chronny::parse_duration(&dwell)).unwrap_or(Duration::zero()))
+}
+
+fn sampling(conn: &mut Connection, interval: Option<&str>, dwell: Option<&str>) -> Result<(), Error> {
+    if let Some(dwell) = dwell {
+        chronny::parse_duration(dwell).ok_or(Error::InvalidDuration(dwell.into()))?;
+        set_setting(conn, "min_dwell", dwell)?;
+    }
+    if let Some(interval) = interval {
+        set_setting(conn, "sample_interval", interval)?;
+    }
+    if interval.is_none() && dwell.is_none() {
+        match sample_interval(conn)? {
+            Some(interval) => println!("Interval: {}ms", interval),
+            None => println!("Interval: 100ms, or 1000ms without a display"),
+        }
+        println!("Minimum dwell time: {}", setting(conn, "min_dwell")?.unwrap_or("none".into()));
+    }
+    Ok(())
+}
+
+fn working_hours(conn: &mut Connection, hours: Option<&str>) -> Result<(), Error> {
+    if let Some(hours) = hours {
+        chronny::parse_working_hours(hours).ok_or(Error::InvalidWorkingHours(hours.into()))?;
+        set_setting(conn, "working_hours", hours)?;
+    } else {
+        println!("{}", setting(conn, "working_hours")?.unwrap_or(DEFAULT_WORKING_HOURS.into()));
+    }
+    Ok(())
+}
+
+fn load_working_hours(conn: &Connection) -> Result<Vec<chronny::WorkingHours>, Error> {
+    let hours = setting(conn, "working_hours")?.unwrap_or(DEFAULT_WORKING_HOURS.into());
+    chronny::parse_working_hours(&hours).ok_or(Error::InvalidWorkingHours(hours.clone()))
+}
+
+/// The parts of the working hours between `since` and `until` where nothing was tracked, for any
+/// project, leaving out days off and anything shorter than `min`.
+fn find_gaps(conn: &Connection,
+             since: DateTime<Local>,
+             until: DateTime<Local>,
+             min: Duration)
+             -> Result<Vec<(DateTime<Local>, DateTime<Local>)>, Error> {
+    let hours = load_working_hours(conn)?;
+    let off = days_off(conn, since.date().naive_local(), until.date().naive_local())?;
+    let mut stmnt = conn.prepare("SELECT start, end FROM timeperiods WHERE start < ? AND end > ? ORDER BY start")?;
+    let rows = stmnt.query_map(&[&until, &since], |row| (row.get(0), row.get(1)))?;
+    let mut tracked = vec![];
+    for row in rows {
+        let period: (DateTime<Local>, DateTime<Local>) = row?;
+        tracked.push(period);
+    }
+    let mut gaps = vec![];
+    for (start, end) in chronny::working_intervals(&hours, since, until) {
+        if off.contains(&start.date().naive_local()) {
+            continue;
+        }
+        let mut gap_start = start;
+        for &(s, e) in tracked.iter().filter(|&&(s, e)| s < end && e > start) {
+            if s > gap_start {
+                gaps.push((gap_start, s));
+            }
+            if e > gap_start {
+                gap_start = e;
+            }
+        }
+        if gap_start < end {
+            gaps.push((gap_start, end));
+        }
+    }
+    gaps.retain(|&(start, end)| end - start >= min);
+    Ok(gaps)
+}
+
+fn gaps(conn: &mut Connection,
+        week: bool,
+        since: Option<&str>,
+        until: Option<&str>,
+        min: &str,
+        assign: bool)
+        -> Result<(), Error> {
+    let min = chronny::parse_duration(min).ok_or(Error::InvalidDuration(min.into()))?;
+    // looking for gaps since the year 1 isn't useful, so default to the past week
+    let week = week || since.is_none();
+    let (since, until) = parse_range(week, since, until)?;
+    let until = if until > Local::now() { Local::now() } else { until };
+    let gaps = find_gaps(conn, since, until, min)?;
+    if gaps.is_empty() {
+        println!("No gaps");
+        return Ok(());
+    }
+    if !assign {
+        let mut table = Table::with_headers(vec!["Day".into(), "Start".into(), "End".into(), "Time".into()]);
+        for &(start, end) in &gaps {
+            table.add_simple(vec![format!("{}", start.format("%a %d/%m/%y")),
+                                  format!("{}", start.format("%H:%M")),
+                                  format!("{}", end.format("%H:%M")),
+                                  format_time(hours(end - start))]);
+        }
+        table.add_border_bottom();
+        table.print();
+        return Ok(());
+    }
+
+    println!("Enter a project to assign each gap to, optionally followed by a description after a \
+              colon, or leave it blank to skip the gap");
+    let mut assigned = vec![];
+    'gaps: for (start, end) in gaps {
+        loop {
+            print!("{} {}-{} {}: ",
+                   start.format("%a %d/%m/%y"),
+                   start.format("%H:%M"),
+                   end.format("%H:%M"),
+                   format_time(hours(end - start)));
+            let _ = io::stdout().flush();
+            let mut s = String::new();
+            if io::stdin().read_line(&mut s).unwrap_or(0) == 0 {
+                println!("");
+                break 'gaps;
+            }
+            let mut parts = s.trim().splitn(2, ':');
+            let project = parts.next().unwrap().trim();
+            let description = parts.next().map(|d| d.trim().to_string()).filter(|d| !d.is_empty());
+            if project.is_empty() {
+                continue 'gaps;
+            }
+            match find_project(conn, project) {
+                Ok((project_id, true)) => {
+                    assigned.push((project_id, start, end, description));
+                    continue 'gaps;
+                }
+                Ok((_, false)) => println!("Project {} is inactive", project),
+                Err(Error::ProjectNotFound(_)) => println!("Project {} not found", project),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+    if assigned.is_empty() {
+        return Ok(());
+    }
+    let tx = conn.transaction()?;
+    for &(project_id, start, end, ref description) in &assigned {
+        tx.execute("INSERT INTO timeperiods(project_id, start, end, description, billable)
+                    SELECT ?1, ?2, ?3, ?4, billable FROM projects WHERE id=?1",
+                   &[&project_id, &start, &end, description])?;
+    }
+    tx.commit()?;
+    println!("Added {} periods", assigned.len());
+    Ok(())
+}
+
+fn confirm(question: &str) -> bool {
+    print!("{} [y/N] ", question);
+    let _ = io::stdout().flush();
+    let mut s = String::new();
+    io::stdin().read_line(&mut s).unwrap();
+    s.trim().to_lowercase().starts_with('y')
+}
+
+fn parse_edit_line(line: &str) -> Result<EditLine, Error> {
+    edit::parse(line).ok_or(Error::InvalidEditLine(line.into()))
+}
+
+/// Creates a file only this user can read for the periods to be edited in. The name is made up
+/// fresh each time, as on a shared temporary directory anyone could have made the obvious one.
+fn create_edit_file() -> Result<(PathBuf, fs::File), Error> {
+    for attempt in 0.. {
+        let nanos = time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH)
+            .map(|since| since.subsec_nanos())
+            .unwrap_or(0);
+        let path = env::temp_dir().join(format!("timmy-edit-{}-{:x}.txt", process::id(), nanos ^ attempt));
+        match fs::OpenOptions::new().write(true).create_new(true).mode(0o600).open(&path) {
+            Ok(file) => return Ok((path, file)),
+            Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists && attempt < 100 => continue,
+            Err(_) => return Err(Error::Editor(format!("{:?}", path))),
+        }
+    }
+    unreachable!();
+}
+
+fn run_editor(path: &Path) -> Result<(), Error> {
+    let editor = env::var("VISUAL").or(env::var("EDITOR")).unwrap_or("vi".into());
+    // $EDITOR can have arguments, eg. "code --wait"
+    let mut words = editor.split_whitespace();
+    let program = words.next().ok_or(Error::Editor(editor.clone()))?;
+    let status = Command::new(program)
+        .args(&words.collect::<Vec<_>>())
+        .arg(path)
+        .status()
+        .map_err(|_| Error::Editor(editor.clone()))?;
+    if !status.success() {
+        return Err(Error::Editor(editor.clone()));
+    }
+    Ok(())
+}
+
+fn edit(conn: &mut Connection,
+        day: Option<&str>,
+        week: bool,
+        since: Option<&str>,
+        until: Option<&str>,
+        policy: OverlapPolicy)
+        -> Result<(), Error> {
+    let (since, until) = if let Some(day) = day {
+        let day = chronny::parse_datetime(day, Local::now()).ok_or(Error::InvalidDateTime(day.into()))?;
+        let day = day.date().and_hms(0, 0, 0);
+        (day, day + Duration::days(1))
+    } else {
+        parse_range(week, since, until)?
+    };
+
+    let mut originals = vec![];
+    {
+        let mut stmnt = conn.prepare("SELECT timeperiods.id, projects.name, start, end, description
+                                      FROM timeperiods
+                                      JOIN projects ON projects.id=timeperiods.project_id
+                                      WHERE start >= ? AND start < ?
+                                      ORDER BY start")?;
+        let rows = stmnt.query_map(&[&since, &until], |row| {
+            EditLine {
+                id: Some(row.get(0)),
+                project: row.get(1),
+                start: row.get(2),
+                end: row.get(3),
+                description: row.get(4),
+            }
+        })?;
+        for row in rows {
+            // go through the text format so that an untouched line compares equal even though the
+            // seconds are lost
+            originals.push(parse_edit_line(&edit::render(&row?))?);
+        }
+    }
+
+    let mut text = String::from("# Each line is a period: <id> <project> <start> <end> <description>\n\
+                                 # Times are dd/mm/yy HH:MM. Delete a line to delete the period, or \
+                                 add a line with the id \"new\" to add one. Put project names with \
+                                 spaces in double quotes.\n");
+    for line in &originals {
+        text.push_str(&edit::render(line));
+        text.push('\n');
+    }
+    let (path, mut file) = create_edit_file()?;
+    let written = file.write_all(text.as_bytes()).map_err(|_| Error::Editor(format!("{:?}", path)));
+    drop(file);
+    let text = written.and_then(|_| run_editor(&path)).and_then(|_| {
+        let mut text = String::new();
+        fs::File::open(&path)
+            .and_then(|mut file| file.read_to_string(&mut text))
+            .map_err(|_| Error::Editor(format!("{:?}", path)))?;
+        Ok(text)
+    });
+    let _ = fs::remove_file(&path);
+    let text = text?;
+
+    let mut edited = vec![];
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = parse_edit_line(line)?;
+        if let Some(id) = line.id {
+            if !originals.iter().any(|orig| orig.id == Some(id)) {
+                return Err(Error::PeriodNotFound(id));
+            }
+            if edited.iter().any(|other: &EditLine| other.id == Some(id)) {
+                return Err(Error::InvalidEditLine(edit::render(&line)));
+            }
+        }
+        edited.push(line);
+    }
+
+    let deletions: Vec<&EditLine> =
+        originals.iter().filter(|orig| !edited.iter().any(|line| line.id == orig.id)).collect();
+    let updates: Vec<(&EditLine, &EditLine)> =
+        edited.iter()
+              .filter_map(|line| originals.iter().find(|orig| line.id.is_some() && orig.id == line.id)
+                                                 .map(|orig| (orig, line)))
+              .filter(|&(orig, line)| orig != line)
+              .collect();
+    let inserts: Vec<&EditLine> = edited.iter().filter(|line| line.id.is_none()).collect();
+    if deletions.is_empty() && updates.is_empty() && inserts.is_empty() {
+        println!("No changes");
+        return Ok(());
+    }
+    for line in deletions.iter().cloned().chain(updates.iter().map(|&(orig, _)| orig)) {
+        check_unlocked(conn, line.id.unwrap())?;
+    }
+    for line in updates.iter().map(|&(_, line)| line).chain(inserts.iter().cloned()) {
+        check_times(line.start, line.end)?;
+    }
+    for line in &deletions {
+        println!("delete: {}", edit::render(line));
+    }
+    for &(orig, line) in &updates {
+        println!("change: {}", edit::render(orig));
+        println!("    to: {}", edit::render(line));
+    }
+    for line in &inserts {
+        println!("   add: {}", edit::render(line));
+    }
+    if !confirm("Apply these changes?") {
+        return Ok(());
+    }
+
+    let tx = conn.transaction()?;
+    for line in deletions {
+        let id = line.id.unwrap();
+        tx.execute("DELETE FROM commits WHERE timeperiod_id=?", &[&id])?;
+        tx.execute("DELETE FROM program_segments WHERE timeperiod_id=?", &[&id])?;
+        tx.execute("DELETE FROM pomodoros WHERE timeperiod_id=?", &[&id])?;
+        tx.execute("DELETE FROM timeperiods WHERE id=?", &[&id])?;
+    }
+    // Every change is made before looking for overlaps, so periods moved together don't trip over
+    // each other's old times
+    let mut moved = vec![];
+    for (orig, line) in updates {
+        let id = line.id.unwrap();
+        if orig.project != line.project {
+            let (proj_id, _) = find_project(&tx, &line.project)?;
+            tx.execute("UPDATE timeperiods SET project_id=? WHERE id=?", &[&proj_id, &id])?;
+            tx.execute("UPDATE commits SET project_id=? WHERE timeperiod_id=?", &[&proj_id, &id])?;
+            tx.execute("UPDATE pomodoros SET project_id=? WHERE timeperiod_id=?", &[&proj_id, &id])?;
+        }
+        if orig.description != line.description {
+            tx.execute("UPDATE timeperiods SET description=? WHERE id=?", &[&line.description, &id])?;
+        }
+        if orig.start != line.start || orig.end != line.end {
+            reshape_period(&tx, id, &[(line.start, line.end)])?;
+            moved.push(id);
+        }
+    }
+    for line in inserts {
+        let (proj_id, _) = find_project(&tx, &line.project)?;
+        tx.execute("INSERT INTO timeperiods(project_id, start, end, description, billable)
+                    SELECT ?1, ?2, ?3, ?4, billable FROM projects WHERE id=?1",
+                   &[&proj_id, &line.start, &line.end, &line.description])?;
+        moved.push(tx.last_insert_rowid());
+    }
+    for id in moved {
+        // trimming an earlier period can have already removed or shortened this one
+        let (start, end) = match tx.query_row("SELECT start, end FROM timeperiods WHERE id=?",
+                                              &[&id],
+                                              |row| (row.get(0), row.get(1))) {
+            Ok(times) => times,
+            Err(rusqlite::Error::QueryReturnedNoRows) => continue,
+            Err(e) => return Err(Error::from(e)),
+        };
+        let pieces = resolve_overlaps(&tx, start, end, Some(id), policy)?;
+        reshape_period(&tx, id, &pieces)?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+fn recur_add(conn: &mut Connection,
+             project: &str,
+             schedule: &str,
+             description: Option<&str>,
+             since: Option<&str>)
+             -> Result<(), Error> {
+    let (proj_id, active) = find_project(conn, project)?;
+    if !active {
+        return Err(Error::InactiveProject(project.into()));
+    }
+    chronny::parse_schedule(schedule).ok_or(Error::InvalidSchedule(schedule.into()))?;
+    // a day's occurrence counts even when its time has already gone by when the entry is added
+    let since = if let Some(since) = since {
+        chronny::parse_datetime(since, Local::now()).ok_or(Error::InvalidDateTime(since.into()))?
+    } else {
+        Local::now()
+    };
+    let since = since.date().and_hms(0, 0, 0);
+    conn.execute("INSERT INTO recurring(project_id, description, schedule, since) VALUES (?,?,?,?)",
+                 &[&proj_id, &description, &schedule, &since])?;
+    Ok(())
+}
+
+fn recur_list(conn: &mut Connection) -> Result<(), Error> {
+    let mut stmnt = conn.prepare("SELECT recurring.id, projects.name, schedule, description
+                                  FROM recurring
+                                  JOIN projects ON projects.id=recurring.project_id
+                                  ORDER BY recurring.id")?;
+    let rows = stmnt.query_map(&[], |row| (row.get(0), row.get(1), row.get(2), row.get(3)))?;
+    let mut table = Table::with_headers(vec!["Id".into(), "Project".into(), "Schedule".into(),
+                                             "Description".into()]);
+    for row in rows {
+        let (id, project, schedule, description): (i64, String, String, Option<String>) = row?;
+        table.add_simple(vec![format!("{}", id), project, schedule, description.unwrap_or("".into())]);
+    }
+    table.add_border_bottom();
+    table.print();
+    Ok(())
+}
+
+fn recur_remove(conn: &mut Connection, id: i64) -> Result<(), Error> {
+    let tx = conn.transaction()?;
+    if tx.execute("DELETE FROM recurring WHERE id=?", &[&id])? == 0 {
+        return Err(Error::RecurringNotFound(id));
+    }
+    tx.execute("DELETE FROM recurring_occurrences WHERE recurring_id=?", &[&id])?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Adds a period for every occurrence of the recurring entries that has finished and hasn't been
+/// added before, leaving out finished projects. Occurrences are remembered even if their period is
+/// deleted later, so they don't come back.
+fn recur_apply(conn: &mut Connection, policy: OverlapPolicy) -> Result<(), Error> {
+    let now = Local::now();
+    let tx = conn.transaction()?;
+    let entries: Vec<(i64, i64, String, Option<String>, String, DateTime<Local>)> = {
+        let mut stmnt = tx.prepare("SELECT recurring.id, project_id, projects.name, description,
+                                           schedule, since
+                                    FROM recurring
+                                    JOIN projects ON projects.id=recurring.project_id
+                                    WHERE projects.active")?;
+        let rows = stmnt.query_map(&[], |row| (row.get(0), row.get(1), row.get(2), row.get(3),
+                                               row.get(4), row.get(5)))?;
+        let mut entries = vec![];
+        for row in rows {
+            entries.push(row?);
+        }
+        entries
+    };
+    let mut added = 0;
+    for (id, proj_id, project, description, schedule, since) in entries {
+        let schedule = chronny::parse_schedule(&schedule).ok_or(Error::InvalidSchedule(schedule.clone()))?;
+        for start in schedule.occurrences(since, now) {
+            let end = start + schedule.duration;
+            if end > now {
+                continue;
+            }
+            let seen: i64 = tx.query_row("SELECT COUNT(*) FROM recurring_occurrences
+                                          WHERE recurring_id=? AND start=?",
+                                         &[&id, &start],
+                                         |row| row.get(0))?;
+            if seen > 0 {
+                continue;
+            }
+            let pieces = match resolve_overlaps(&tx, start, end, None, policy) {
+                Ok(pieces) => pieces,
+                Err(Error::Overlap(_)) | Err(Error::PeriodCovered) => {
+                    println!("Skipping {} {}-{} as it overlaps other periods",
+                             project, start.format("%d/%m/%y %H:%M"), end.format("%H:%M"));
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            for (start, end) in pieces {
+                tx.execute("INSERT INTO timeperiods(project_id, start, end, description, billable)
+                            SELECT ?1, ?2, ?3, ?4, billable FROM projects WHERE id=?1",
+                           &[&proj_id, &start, &end, &description])?;
+            }
+            tx.execute("INSERT INTO recurring_occurrences(recurring_id, start) VALUES (?,?)",
+                       &[&id, &start])?;
+            println!("Added {} {}-{}", project, start.format("%d/%m/%y %H:%M"), end.format("%H:%M"));
+            added += 1;
+        }
+    }
+    tx.commit()?;
+    println!("Added {} periods", added);
+    Ok(())
+}
+
+fn period_id_arg<'a, 'b>(name: &'a str, help: &'b str) -> Arg<'a, 'b> {
+    Arg::with_name(name)
+        .help(help)
+        .required(true)
+        .validator(|id| id.parse::<i64>().map(|_| ()).map_err(|_| format!("{} is not a period id", id)))
+}
+
+fn billable_flag(matches: &clap::ArgMatches) -> Option<bool> {
+    if matches.is_present("billable") {
+        Some(true)
+    } else if matches.is_present("non-billable") {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+fn main() {
+    env_logger::init().unwrap();
+
+    let mut conn = open_connection().unwrap();
+    let matches = App::new("Timmy")
+        .version("0.1")
+        .author("Matthew Hall")
         .about("Time tracker")
         .subcommand(SubCommand::with_name("new")
             .about("Creates a new project")
@@ -594,7 +3264,10 @@ fn main() {
                 .short("t")
                 .long("tags")
                 .help("comma separated list of tags")
-                .takes_value(true)))
+                .takes_value(true))
+            .arg(Arg::with_name("non-billable")
+                .long("non-billable")
+                .help("don't bill time tracked on this project unless told to")))
         .subcommand(SubCommand::with_name("finish")
             .about("Makes a project inactive")
             .arg(Arg::with_name("NAME")
@@ -608,8 +3281,8 @@ fn main() {
         .subcommand(SubCommand::with_name("track")
             .about("Start tracking a time period")
             .arg(Arg::with_name("PROJECT")
-                .help("the project to start tracking time for")
-                .required(true))
+                .help("the project to start tracking time for, by default the one the project rules \
+                       pick for the current directory"))
             .arg(Arg::with_name("description")
                 .short("d")
                 .long("description")
@@ -623,13 +3296,37 @@ fn main() {
             .arg(Arg::with_name("end")
                  .short("e")
                  .long("end")
-                 .help("When to end")
+                 .help("When to end, relative to the start. Use +<duration> for a time after the \
+                        start, eg. +90m")
                  .takes_value(true)
                  .requires("start"))
+            .arg(Arg::with_name("for")
+                 .long("for")
+                 .help("How long to track for, eg. 1h30m. Without --start the period ends now")
+                 .takes_value(true)
+                 .conflicts_with("end"))
+            .arg(Arg::with_name("pomodoro")
+                 .long("pomodoro")
+                 .value_name("WORK/BREAK")
+                 .help("Work in pomodoros, 25 minutes long with 5 minute breaks unless given other \
+                        lengths, eg. 50m/10m. As the lengths are optional, give the project first")
+                 .takes_value(true)
+                 .min_values(0)
+                 .max_values(1)
+                 .conflicts_with_all(&["end", "for"]))
+            .arg(Arg::with_name("billable")
+                 .long("billable")
+                 .help("Bill this period, even if the project isn't billed by default"))
+            .arg(Arg::with_name("non-billable")
+                 .long("non-billable")
+                 .help("Don't bill this period, eg. for internal meetings")
+                 .conflicts_with("billable"))
             .arg(Arg::with_name("no program")
                  .short("n")
                  .long("noprogram")
-                 .help("Don't track program usage")))
+                 .help("Don't track program usage"))
+            .arg(overlap_arg())
+            .arg(allow_overlap_arg()))
         .subcommand(SubCommand::with_name("git")
             .about("go through each time period and store the commits that happened during that \
                     time. timmy track automatically does this when you quit it for that \
@@ -668,33 +3365,640 @@ fn main() {
                  .conflicts_with_all(&["since", "until"]))
             .arg(Arg::with_name("short")
                  .long("short")
-                 .help("omit the recent activity")))
+                 .help("omit the recent activity"))
+            .arg(Arg::with_name("raw")
+                 .long("raw")
+                 .help("show the time actually tracked rather than the rounded time")))
+        .subcommand(SubCommand::with_name("customer")
+            .about("Show the time spent on each of a customer's projects")
+            .arg(Arg::with_name("NAME")
+                .help("the customer to show")
+                .required(true))
+            .arg(Arg::with_name("since")
+                 .short("s")
+                 .long("since")
+                 .help("the date and time from which to count time")
+                 .takes_value(true))
+            .arg(Arg::with_name("until")
+                 .short("u")
+                 .long("until")
+                 .help("the date and time until which to count time")
+                 .takes_value(true))
+            .arg(Arg::with_name("week")
+                 .short("w")
+                 .long("week")
+                 .help("count time in the past week")
+                 .conflicts_with_all(&["since", "until"]))
+            .arg(Arg::with_name("raw")
+                 .long("raw")
+                 .help("show the time actually tracked rather than the rounded time")))
+        .subcommand(SubCommand::with_name("rate")
+            .about("Set the hourly rate a project, or all of a customer's projects, is billed at")
+            .setting(AppSettings::SubcommandRequired)
+            .subcommand(SubCommand::with_name("set")
+                .about("Set the rate for a project or customer. A project's own rate takes \
+                        precedence over its customer's")
+                .arg(Arg::with_name("RATE")
+                     .help("the hourly rate, eg. 45.50")
+                     .required(true))
+                .arg(Arg::with_name("project")
+                     .short("p")
+                     .long("project")
+                     .takes_value(true))
+                .arg(Arg::with_name("customer")
+                     .short("c")
+                     .long("customer")
+                     .takes_value(true))
+                .group(ArgGroup::with_name("target")
+                     .args(&["project", "customer"])
+                     .required(true)))
+            .subcommand(SubCommand::with_name("clear")
+                .about("Remove the rate for a project or customer")
+                .arg(Arg::with_name("project")
+                     .short("p")
+                     .long("project")
+                     .takes_value(true))
+                .arg(Arg::with_name("customer")
+                     .short("c")
+                     .long("customer")
+                     .takes_value(true))
+                .group(ArgGroup::with_name("target")
+                     .args(&["project", "customer"])
+                     .required(true)))
+            .subcommand(SubCommand::with_name("list")
+                .about("List the rates")))
+        .subcommand(SubCommand::with_name("invoice")
+            .about("Invoice a customer for the billable time that hasn't been invoiced yet")
+            .arg(Arg::with_name("CUSTOMER")
+                .help("the customer to invoice")
+                .required(true))
+            .arg(Arg::with_name("since")
+                 .short("s")
+                 .long("since")
+                 .help("the date and time from which to invoice")
+                 .takes_value(true))
+            .arg(Arg::with_name("until")
+                 .short("u")
+                 .long("until")
+                 .help("the date and time until which to invoice")
+                 .takes_value(true))
+            .arg(Arg::with_name("week")
+                 .short("w")
+                 .long("week")
+                 .help("invoice the past week")
+                 .conflicts_with_all(&["since", "until"]))
+            .arg(Arg::with_name("currency")
+                 .long("currency")
+                 .help("the currency to show amounts in, eg. GBP")
+                 .takes_value(true))
+            .arg(Arg::with_name("due")
+                 .long("due")
+                 .help("when the invoice must be paid by. Defaults to 30 days from now")
+                 .takes_value(true))
+            .arg(Arg::with_name("format")
+                 .short("f")
+                 .long("format")
+                 .help("how to render the invoice. Defaults to text")
+                 .takes_value(true)
+                 .possible_values(&["text", "markdown", "html"]))
+            .arg(Arg::with_name("output")
+                 .short("o")
+                 .long("output")
+                 .help("the file to write the invoice to instead of printing it")
+                 .takes_value(true))
+            .arg(Arg::with_name("dry run")
+                 .long("dry-run")
+                 .help("show the invoice without numbering it or recording the periods as invoiced")))
+        .subcommand(SubCommand::with_name("expense")
+            .about("Record expenses to pass on to a customer")
+            .setting(AppSettings::SubcommandRequired)
+            .subcommand(SubCommand::with_name("add")
+                .about("Add an expense to a project")
+                .arg(Arg::with_name("PROJECT")
+                     .help("the project the expense was for")
+                     .required(true))
+                .arg(Arg::with_name("AMOUNT")
+                     .help("how much it cost, eg. 12.50")
+                     .required(true))
+                .arg(Arg::with_name("date")
+                     .short("d")
+                     .long("date")
+                     .help("when it was paid. Defaults to now")
+                     .takes_value(true))
+                .arg(Arg::with_name("currency")
+                     .long("currency")
+                     .help("the currency it was paid in, eg. GBP")
+                     .takes_value(true))
+                .arg(Arg::with_name("category")
+                     .short("c")
+                     .long("category")
+                     .help("what kind of expense it is, eg. travel")
+                     .takes_value(true))
+                .arg(Arg::with_name("note")
+                     .short("n")
+                     .long("note")
+                     .takes_value(true)))
+            .subcommand(SubCommand::with_name("list")
+                .about("List expenses along with their ids")
+                .arg(Arg::with_name("PROJECT")
+                     .help("only show expenses for this project"))
+                .arg(Arg::with_name("since")
+                     .short("s")
+                     .long("since")
+                     .help("the date and time from which to show expenses")
+                     .takes_value(true))
+                .arg(Arg::with_name("until")
+                     .short("u")
+                     .long("until")
+                     .help("the date and time until which to show expenses")
+                     .takes_value(true))
+                .arg(Arg::with_name("week")
+                     .short("w")
+                     .long("week")
+                     .help("show expenses in the past week")
+                     .conflicts_with_all(&["since", "until"])))
+            .subcommand(SubCommand::with_name("delete")
+                .about("Delete an expense that hasn't been invoiced")
+                .arg(Arg::with_name("ID")
+                     .required(true)
+                     .validator(|id| id.parse::<i64>().map(|_| ()).map_err(|_| format!("{} is not an expense id", id))))))
+        .subcommand(SubCommand::with_name("invoices")
+            .about("List invoices and whether they have been paid")
+            .arg(Arg::with_name("unpaid")
+                 .long("unpaid")
+                 .help("only show invoices that haven't been paid, and how much each customer owes"))
+            .subcommand(SubCommand::with_name("status")
+                .about("Change the status of an invoice. A sent invoice shows as overdue once it \
+                        is past its due date")
+                .arg(Arg::with_name("NUMBER")
+                     .help("the invoice number")
+                     .required(true)
+                     .validator(|n| n.parse::<i64>().map(|_| ()).map_err(|_| format!("{} is not an invoice number", n))))
+                .arg(Arg::with_name("STATUS")
+                     .required(true)
+                     .possible_values(&["draft", "sent", "paid", "overdue"]))))
+        .subcommand(SubCommand::with_name("periods")
+            .about("List time periods along with their ids")
+            .arg(Arg::with_name("PROJECT")
+                .help("only show periods for this project"))
+            .arg(Arg::with_name("since")
+                 .short("s")
+                 .long("since")
+                 .help("the date and time from which to show periods")
+                 .takes_value(true))
+            .arg(Arg::with_name("until")
+                 .short("u")
+                 .long("until")
+                 .help("the date and time until which to show periods")
+                 .takes_value(true))
+            .arg(Arg::with_name("week")
+                 .short("w")
+                 .long("week")
+                 .help("show periods in the past week")
+                 .conflicts_with_all(&["since", "until"])))
+        .subcommand(SubCommand::with_name("period")
+            .about("Change a time period. Use timmy periods to find its id")
+            .setting(AppSettings::SubcommandRequired)
+            .subcommand(SubCommand::with_name("show")
+                .about("Show a period with its commits, program usage and how focused it was")
+                .arg(period_id_arg("ID", "the period to show"))
+                .arg(Arg::with_name("timeline")
+                     .short("t")
+                     .long("timeline")
+                     .help("list which program had focus when")))
+            .subcommand(SubCommand::with_name("edit")
+                .about("Change the start, end, description or billing of a period")
+                .arg(period_id_arg("ID", "the period to edit"))
+                .arg(Arg::with_name("start")
+                     .short("s")
+                     .long("start")
+                     .help("the new start, relative to the old one")
+                     .takes_value(true))
+                .arg(Arg::with_name("end")
+                     .short("e")
+                     .long("end")
+                     .help("the new end, relative to the start. Use +<duration> for a time after \
+                            the start, eg. +90m")
+                     .takes_value(true))
+                .arg(Arg::with_name("description")
+                     .short("d")
+                     .long("description")
+                     .help("the new description")
+                     .takes_value(true))
+                .arg(Arg::with_name("billable")
+                     .long("billable")
+                     .help("bill the period"))
+                .arg(Arg::with_name("non-billable")
+                     .long("non-billable")
+                     .help("don't bill the period")
+                     .conflicts_with("billable"))
+                .arg(overlap_arg())
+                .arg(allow_overlap_arg()))
+            .subcommand(SubCommand::with_name("move")
+                .about("Move a period, along with its commits, to another project")
+                .arg(period_id_arg("ID", "the period to move"))
+                .arg(Arg::with_name("PROJECT")
+                     .help("the project to move it to")
+                     .required(true)))
+            .subcommand(SubCommand::with_name("split")
+                .about("Split a period in two")
+                .arg(period_id_arg("ID", "the period to split"))
+                .arg(Arg::with_name("TIME")
+                     .help("when the second period should start")
+                     .required(true)))
+            .subcommand(SubCommand::with_name("merge")
+                .about("Merge two periods of the same project into one, including the time between \
+                        them")
+                .arg(period_id_arg("ID", "the period to keep"))
+                .arg(period_id_arg("OTHER", "the period to merge into it"))
+                .arg(overlap_arg())
+                .arg(allow_overlap_arg()))
+            .subcommand(SubCommand::with_name("delete")
+                .about("Delete a period and its commits")
+                .arg(period_id_arg("ID", "the period to delete")))
+            .subcommand(SubCommand::with_name("unlock")
+                .about("Allow an invoiced period to be changed")
+                .arg(period_id_arg("ID", "the period to unlock")))
+            .subcommand(SubCommand::with_name("lock")
+                .about("Stop a period from being changed")
+                .arg(period_id_arg("ID", "the period to lock"))))
+        .subcommand(SubCommand::with_name("edit")
+            .about("Edit periods in $EDITOR")
+            .arg(Arg::with_name("day")
+                 .long("day")
+                 .help("the day to edit")
+                 .takes_value(true)
+                 .conflicts_with_all(&["since", "until", "week"]))
+            .arg(Arg::with_name("since")
+                 .short("s")
+                 .long("since")
+                 .help("the date and time from which to edit periods")
+                 .takes_value(true))
+            .arg(Arg::with_name("until")
+                 .short("u")
+                 .long("until")
+                 .help("the date and time until which to edit periods")
+                 .takes_value(true))
+            .arg(Arg::with_name("week")
+                 .short("w")
+                 .long("week")
+                 .help("edit periods in the past week")
+                 .conflicts_with_all(&["since", "until"]))
+            .arg(overlap_arg())
+            .arg(allow_overlap_arg()))
+        .subcommand(SubCommand::with_name("recur")
+            .about("Manage recurring entries, like a daily stand-up")
+            .setting(AppSettings::SubcommandRequired)
+            .subcommand(SubCommand::with_name("add")
+                .about("Add a recurring entry")
+                .arg(Arg::with_name("PROJECT")
+                     .help("the project to track the time for")
+                     .required(true))
+                .arg(Arg::with_name("SCHEDULE")
+                     .help("when it happens, eg. \"weekdays 09:30 for 15m\" or \"mon,thu 14:00 for 1h\"")
+                     .required(true))
+                .arg(Arg::with_name("description")
+                     .short("d")
+                     .long("description")
+                     .help("a description for each period")
+                     .takes_value(true))
+                .arg(Arg::with_name("since")
+                     .short("s")
+                     .long("since")
+                     .help("when the entry started recurring. Defaults to now")
+                     .takes_value(true)))
+            .subcommand(SubCommand::with_name("list")
+                .about("List the recurring entries"))
+            .subcommand(SubCommand::with_name("remove")
+                .about("Remove a recurring entry. Periods already added are kept")
+                .arg(Arg::with_name("ID")
+                     .help("the recurring entry to remove")
+                     .required(true)
+                     .validator(|id| id.parse::<i64>().map(|_| ()).map_err(|_| format!("{} is not an id", id)))))
+            .subcommand(SubCommand::with_name("apply")
+                .about("Add periods for the recurring entries up to now")
+                .arg(overlap_arg())
+                .arg(allow_overlap_arg())))
         .subcommand(SubCommand::with_name("weeks")
-            .about("show time spent per week")
+            .about("show time spent per week, against the weekly target if there is one")
             .arg(Arg::with_name("PROJECT")
-                .help("the project to show")
-                .required(true))
+                .help("the project to show. Without one the time spent on all projects is shown \
+                       against the contracted hours"))
             .arg(Arg::with_name("short")
                  .long("short")
-                 .help("show the short view")))
+                 .help("show the short view"))
+            .arg(Arg::with_name("raw")
+                 .long("raw")
+                 .help("show the time actually tracked rather than the rounded time")))
+        .subcommand(SubCommand::with_name("target")
+            .about("Set how many hours a week should be spent on a project, or the contracted \
+                    hours across all projects")
+            .setting(AppSettings::SubcommandRequired)
+            .subcommand(SubCommand::with_name("set")
+                .about("Set a weekly target")
+                .arg(Arg::with_name("HOURS")
+                     .help("the hours per week, eg. 37.5")
+                     .required(true))
+                .arg(Arg::with_name("project")
+                     .short("p")
+                     .long("project")
+                     .help("the project the target is for. Without one this sets the contracted hours")
+                     .takes_value(true)))
+            .subcommand(SubCommand::with_name("clear")
+                .about("Remove a weekly target")
+                .arg(Arg::with_name("project")
+                     .short("p")
+                     .long("project")
+                     .help("the project the target is for. Without one this clears the contracted hours")
+                     .takes_value(true)))
+            .subcommand(SubCommand::with_name("list")
+                .about("List the weekly targets")))
+        .subcommand(SubCommand::with_name("off")
+            .about("Record holidays, sick leave and other time off, which don't count against \
+                    weekly targets")
+            .setting(AppSettings::SubcommandRequired)
+            .subcommand(SubCommand::with_name("add")
+                .about("Take a day, or a range of days, off")
+                .arg(Arg::with_name("START")
+                     .help("the first day off")
+                     .required(true))
+                .arg(Arg::with_name("until")
+                     .short("u")
+                     .long("until")
+                     .help("the last day off. Defaults to just the first day")
+                     .takes_value(true))
+                .arg(Arg::with_name("type")
+                     .short("t")
+                     .long("type")
+                     .help("the kind of time off. Defaults to holiday")
+                     .takes_value(true)
+                     .possible_values(&["holiday", "sick", "other"]))
+                .arg(Arg::with_name("note")
+                     .short("n")
+                     .long("note")
+                     .takes_value(true)))
+            .subcommand(SubCommand::with_name("list")
+                .about("List time off along with the ids"))
+            .subcommand(SubCommand::with_name("remove")
+                .about("Remove some time off")
+                .arg(Arg::with_name("ID")
+                     .required(true)
+                     .validator(|id| id.parse::<i64>().map(|_| ()).map_err(|_| format!("{} is not a time off id", id))))))
+        .subcommand(SubCommand::with_name("category")
+            .about("Sort tracked program usage into categories by program and window title")
+            .setting(AppSettings::SubcommandRequired)
+            .subcommand(SubCommand::with_name("add")
+                .about("Add a rule. The first rule that matches a window decides its category")
+                .arg(Arg::with_name("CATEGORY")
+                     .required(true))
+                .arg(Arg::with_name("program")
+                     .short("p")
+                     .long("program")
+                     .help("only match windows of this program")
+                     .takes_value(true))
+                .arg(Arg::with_name("title")
+                     .short("t")
+                     .long("title")
+                     .help("a regular expression the window title has to match, eg. \
+                            'github\\.com/.*/pull'")
+                     .takes_value(true))
+                .group(ArgGroup::with_name("match")
+                     .args(&["program", "title"])
+                     .multiple(true)
+                     .required(true)))
+            .subcommand(SubCommand::with_name("list")
+                .about("List the rules along with their ids"))
+            .subcommand(SubCommand::with_name("remove")
+                .about("Remove a rule")
+                .arg(Arg::with_name("ID")
+                     .required(true)
+                     .validator(|id| id.parse::<i64>().map(|_| ()).map_err(|_| format!("{} is not a rule id", id))))))
+        .subcommand(SubCommand::with_name("privacy")
+            .about("Keep private windows out of the recorded program usage")
+            .setting(AppSettings::SubcommandRequired)
+            .subcommand(SubCommand::with_name("add")
+                .about("Add a rule. Ignored windows aren't recorded at all and redacted ones are \
+                        recorded as \"private\" without their titles")
+                .arg(Arg::with_name("ACTION")
+                     .required(true)
+                     .possible_values(&["ignore", "redact"]))
+                .arg(Arg::with_name("program")
+                     .short("p")
+                     .long("program")
+                     .help("only match windows of this program")
+                     .takes_value(true))
+                .arg(Arg::with_name("title")
+                     .short("t")
+                     .long("title")
+                     .help("a regular expression the window title has to match, eg. 'Private Browsing'")
+                     .takes_value(true))
+                .group(ArgGroup::with_name("match")
+                     .args(&["program", "title"])
+                     .multiple(true)
+                     .required(true)))
+            .subcommand(SubCommand::with_name("list")
+                .about("List the rules along with their ids"))
+            .subcommand(SubCommand::with_name("remove")
+                .about("Remove a rule")
+                .arg(Arg::with_name("ID")
+                     .required(true)
+                     .validator(|id| id.parse::<i64>().map(|_| ()).map_err(|_| format!("{} is not a rule id", id))))))
+        .subcommand(SubCommand::with_name("purge-programs")
+            .about("Delete the program usage recorded in a range")
+            .arg(Arg::with_name("since")
+                 .short("s")
+                 .long("since")
+                 .help("the date and time from which to delete usage")
+                 .takes_value(true))
+            .arg(Arg::with_name("until")
+                 .short("u")
+                 .long("until")
+                 .help("the date and time until which to delete usage")
+                 .takes_value(true)))
+        .subcommand(SubCommand::with_name("focus")
+            .about("Show or set the programs that count as focused work in timmy period show")
+            .arg(Arg::with_name("PROGRAMS")
+                 .help("the programs, eg. emacs gnome-terminal-/nvim")
+                 .multiple(true)
+                 .conflicts_with("clear"))
+            .arg(Arg::with_name("clear")
+                 .short("c")
+                 .long("clear")
+                 .help("stop counting any programs as focused work")))
+        .subcommand(SubCommand::with_name("project-rule")
+            .about("Say which project a directory, repository or window belongs to, so timmy track can \
+                    pick the project and warn when you seem to be working on another")
+            .setting(AppSettings::SubcommandRequired)
+            .subcommand(SubCommand::with_name("add")
+                .about("Add a rule")
+                .arg(Arg::with_name("PROJECT")
+                     .required(true))
+                .arg(Arg::with_name("path")
+                     .short("p")
+                     .long("path")
+                     .help("a directory the project is worked on in, including the directories inside it")
+                     .takes_value(true))
+                .arg(Arg::with_name("remote")
+                     .short("r")
+                     .long("remote")
+                     .help("part of the url of a git remote, eg. github.com/mattyhall/timmy")
+                     .takes_value(true))
+                .arg(Arg::with_name("title")
+                     .short("t")
+                     .long("title")
+                     .help("a regular expression matching the titles of the project's windows")
+                     .takes_value(true))
+                .group(ArgGroup::with_name("match")
+                     .args(&["path", "remote", "title"])
+                     .required(true)))
+            .subcommand(SubCommand::with_name("list")
+                .about("List the rules along with their ids"))
+            .subcommand(SubCommand::with_name("remove")
+                .about("Remove a rule")
+                .arg(Arg::with_name("ID")
+                     .required(true)
+                     .validator(|id| id.parse::<i64>().map(|_| ()).map_err(|_| format!("{} is not a rule id", id))))))
+        .subcommand(SubCommand::with_name("sampling")
+            .about("Show or change how the program tracker samples the focused window")
+            .arg(Arg::with_name("interval")
+                 .short("i")
+                 .long("interval")
+                 .help("how many milliseconds to wait between samples")
+                 .takes_value(true)
+                 .validator(|ms| match ms.parse::<u64>() {
+                     Ok(ms) if ms > 0 => Ok(()),
+                     _ => Err(format!("{} is not a number of milliseconds", ms)),
+                 }))
+            .arg(Arg::with_name("dwell")
+                 .short("d")
+                 .long("dwell")
+                 .help("how long a window needs focus for to be recorded, eg. 2s")
+                 .takes_value(true)))
+        .subcommand(SubCommand::with_name("group")
+            .about("Report programs that are really the same tool under one name")
+            .setting(AppSettings::SubcommandRequired)
+            .subcommand(SubCommand::with_name("add")
+                .about("Add programs to a tool's group")
+                .arg(Arg::with_name("TOOL")
+                     .required(true))
+                .arg(Arg::with_name("PROGRAMS")
+                     .help("the programs, eg. code code-oss codium")
+                     .required(true)
+                     .multiple(true)))
+            .subcommand(SubCommand::with_name("list")
+                .about("List the groups"))
+            .subcommand(SubCommand::with_name("remove")
+                .about("Take programs out of their groups")
+                .arg(Arg::with_name("PROGRAMS")
+                     .required(true)
+                     .multiple(true))))
+        .subcommand(SubCommand::with_name("recover")
+            .about("Close or discard sessions whose track was killed before it finished"))
+        .subcommand(SubCommand::with_name("hours")
+            .about("Show or set your working hours, which timmy gaps looks for untracked time in")
+            .arg(Arg::with_name("HOURS")
+                 .help("the new working hours, eg. \"mon-fri 09:00-17:30\" or \
+                        \"mon-thu 09:00-17:30; fri 09:00-13:00\"")))
+        .subcommand(SubCommand::with_name("gaps")
+            .about("List the untracked time during working hours, across all projects")
+            .arg(Arg::with_name("since")
+                 .short("s")
+                 .long("since")
+                 .help("the date and time from which to look for gaps. Defaults to a week ago")
+                 .takes_value(true))
+            .arg(Arg::with_name("until")
+                 .short("u")
+                 .long("until")
+                 .help("the date and time until which to look for gaps")
+                 .takes_value(true))
+            .arg(Arg::with_name("week")
+                 .short("w")
+                 .long("week")
+                 .help("look for gaps in the past week")
+                 .conflicts_with_all(&["since", "until"]))
+            .arg(Arg::with_name("min")
+                 .long("min")
+                 .help("ignore gaps shorter than this. Defaults to 15m")
+                 .takes_value(true))
+            .arg(Arg::with_name("assign")
+                 .short("a")
+                 .long("assign")
+                 .help("go through the gaps, choosing a project for each one")))
+        .subcommand(SubCommand::with_name("rounding")
+            .about("Set how time is rounded for billing, for a project or for all of a customer's \
+                    projects")
+            .setting(AppSettings::SubcommandRequired)
+            .subcommand(SubCommand::with_name("set")
+                .about("Set the rounding for a project or customer. A project's own rounding takes \
+                        precedence over its customer's")
+                .arg(Arg::with_name("INCREMENT")
+                     .help("what to round to, eg. 15m, or none")
+                     .required(true))
+                .arg(Arg::with_name("project")
+                     .short("p")
+                     .long("project")
+                     .takes_value(true))
+                .arg(Arg::with_name("customer")
+                     .short("c")
+                     .long("customer")
+                     .takes_value(true))
+                .group(ArgGroup::with_name("target")
+                     .args(&["project", "customer"])
+                     .required(true))
+                .arg(Arg::with_name("per")
+                     .long("per")
+                     .help("round each period or each day's total. Defaults to period")
+                     .takes_value(true)
+                     .possible_values(&["period", "day"]))
+                .arg(Arg::with_name("mode")
+                     .long("mode")
+                     .help("round up or to the nearest increment. Defaults to up")
+                     .takes_value(true)
+                     .possible_values(&["up", "nearest"])))
+            .subcommand(SubCommand::with_name("clear")
+                .about("Remove the rounding for a project or customer")
+                .arg(Arg::with_name("project")
+                     .short("p")
+                     .long("project")
+                     .takes_value(true))
+                .arg(Arg::with_name("customer")
+                     .short("c")
+                     .long("customer")
+                     .takes_value(true))
+                .group(ArgGroup::with_name("target")
+                     .args(&["project", "customer"])
+                     .required(true)))
+            .subcommand(SubCommand::with_name("list")
+                .about("List the rounding rules")))
         .get_matches();
 
-    let res = if let Some(matches) = matches.subcommand_matches("new") {
+    let res = if matches.subcommand_matches("recover").is_some() {
+        recover_sessions(&mut conn)
+    } else if let Some(matches) = matches.subcommand_matches("new") {
         create_project(&mut conn,
                        matches.value_of("NAME").unwrap(),
                        matches.value_of("customer"),
-                       matches.value_of("tags").unwrap_or("".into()))
+                       matches.value_of("tags").unwrap_or("".into()),
+                       !matches.is_present("non-billable"))
     } else if let Some(matches) = matches.subcommand_matches("finish") {
         finish_project(&mut conn, matches.value_of("NAME").unwrap())
     } else if let Some(matches) = matches.subcommand_matches("restart") {
         restart_project(&mut conn, matches.value_of("NAME").unwrap())
     } else if let Some(matches) = matches.subcommand_matches("track") {
         track(&mut conn,
-              matches.value_of("PROJECT").unwrap(),
+              matches.value_of("PROJECT"),
               matches.value_of("description"),
               matches.value_of("start"),
               matches.value_of("end"),
-              matches.is_present("no program"))
+              matches.value_of("for"),
+              if matches.is_present("pomodoro") {
+                  Some(matches.value_of("pomodoro").unwrap_or("25m/5m"))
+              } else {
+                  None
+              },
+              billable_flag(matches),
+              matches.is_present("no program"),
+              overlap_policy(matches))
     } else if let Some(matches) = matches.subcommand_matches("git") {
         git(&mut conn, matches.value_of("PROJECT").unwrap())
     } else if let Some(matches) = matches.subcommand_matches("projects") {
@@ -705,12 +4009,246 @@ fn main() {
                 matches.is_present("week"),
                 matches.value_of("since"),
                 matches.value_of("until"),
-                matches.is_present("short"))
+                matches.is_present("short"),
+                matches.is_present("raw"))
+    } else if let Some(matches) = matches.subcommand_matches("customer") {
+        customer(&mut conn,
+                 matches.value_of("NAME").unwrap(),
+                 matches.is_present("week"),
+                 matches.value_of("since"),
+                 matches.value_of("until"),
+                 matches.is_present("raw"))
+    } else if let Some(matches) = matches.subcommand_matches("rate") {
+        if let Some(matches) = matches.subcommand_matches("set") {
+            rate_set(&mut conn,
+                     matches.value_of("project"),
+                     matches.value_of("customer"),
+                     matches.value_of("RATE").unwrap())
+        } else if let Some(matches) = matches.subcommand_matches("clear") {
+            rate_clear(&mut conn, matches.value_of("project"), matches.value_of("customer"))
+        } else if matches.subcommand_matches("list").is_some() {
+            rate_list(&mut conn)
+        } else {
+            unreachable!();
+        }
+    } else if let Some(matches) = matches.subcommand_matches("invoice") {
+        create_invoice(&mut conn,
+                       matches.value_of("CUSTOMER").unwrap(),
+                       matches.is_present("week"),
+                       matches.value_of("since"),
+                       matches.value_of("until"),
+                       matches.value_of("currency"),
+                       matches.value_of("due"),
+                       match matches.value_of("format") {
+                           Some("markdown") => invoice::Format::Markdown,
+                           Some("html") => invoice::Format::Html,
+                           _ => invoice::Format::Text,
+                       },
+                       matches.value_of("output"),
+                       matches.is_present("dry run"))
+    } else if let Some(matches) = matches.subcommand_matches("expense") {
+        if let Some(matches) = matches.subcommand_matches("add") {
+            expense_add(&mut conn,
+                        matches.value_of("PROJECT").unwrap(),
+                        matches.value_of("AMOUNT").unwrap(),
+                        matches.value_of("date"),
+                        matches.value_of("currency"),
+                        matches.value_of("category"),
+                        matches.value_of("note"))
+        } else if let Some(matches) = matches.subcommand_matches("list") {
+            expense_list(&mut conn,
+                         matches.value_of("PROJECT"),
+                         matches.is_present("week"),
+                         matches.value_of("since"),
+                         matches.value_of("until"))
+        } else if let Some(matches) = matches.subcommand_matches("delete") {
+            expense_delete(&mut conn, matches.value_of("ID").unwrap().parse().unwrap())
+        } else {
+            unreachable!();
+        }
+    } else if let Some(matches) = matches.subcommand_matches("invoices") {
+        if let Some(matches) = matches.subcommand_matches("status") {
+            invoice_status(&mut conn,
+                           matches.value_of("NUMBER").unwrap().parse().unwrap(),
+                           matches.value_of("STATUS").unwrap())
+        } else {
+            invoices(&mut conn, matches.is_present("unpaid"))
+        }
+    } else if let Some(matches) = matches.subcommand_matches("periods") {
+        periods(&mut conn,
+                matches.value_of("PROJECT"),
+                matches.is_present("week"),
+                matches.value_of("since"),
+                matches.value_of("until"))
+    } else if let Some(matches) = matches.subcommand_matches("period") {
+        if let Some(matches) = matches.subcommand_matches("show") {
+            period_show(&conn, matches.value_of("ID").unwrap().parse().unwrap(), matches.is_present("timeline"))
+        } else if let Some(matches) = matches.subcommand_matches("edit") {
+            period_edit(&mut conn,
+                        matches.value_of("ID").unwrap().parse().unwrap(),
+                        matches.value_of("start"),
+                        matches.value_of("end"),
+                        matches.value_of("description"),
+                        billable_flag(matches),
+                        overlap_policy(matches))
+        } else if let Some(matches) = matches.subcommand_matches("move") {
+            period_move(&mut conn,
+                        matches.value_of("ID").unwrap().parse().unwrap(),
+                        matches.value_of("PROJECT").unwrap())
+        } else if let Some(matches) = matches.subcommand_matches("split") {
+            period_split(&mut conn,
+                         matches.value_of("ID").unwrap().parse().unwrap(),
+                         matches.value_of("TIME").unwrap())
+        } else if let Some(matches) = matches.subcommand_matches("merge") {
+            period_merge(&mut conn,
+                         matches.value_of("ID").unwrap().parse().unwrap(),
+                         matches.value_of("OTHER").unwrap().parse().unwrap(),
+                         overlap_policy(matches))
+        } else if let Some(matches) = matches.subcommand_matches("delete") {
+            period_delete(&mut conn, matches.value_of("ID").unwrap().parse().unwrap())
+        } else if let Some(matches) = matches.subcommand_matches("unlock") {
+            period_lock(&mut conn, matches.value_of("ID").unwrap().parse().unwrap(), false)
+        } else if let Some(matches) = matches.subcommand_matches("lock") {
+            period_lock(&mut conn, matches.value_of("ID").unwrap().parse().unwrap(), true)
+        } else {
+            unreachable!();
+        }
+    } else if let Some(matches) = matches.subcommand_matches("edit") {
+        edit(&mut conn,
+             matches.value_of("day"),
+             matches.is_present("week"),
+             matches.value_of("since"),
+             matches.value_of("until"),
+             overlap_policy(matches))
+    } else if let Some(matches) = matches.subcommand_matches("recur") {
+        if let Some(matches) = matches.subcommand_matches("add") {
+            recur_add(&mut conn,
+                      matches.value_of("PROJECT").unwrap(),
+                      matches.value_of("SCHEDULE").unwrap(),
+                      matches.value_of("description"),
+                      matches.value_of("since"))
+        } else if matches.subcommand_matches("list").is_some() {
+            recur_list(&mut conn)
+        } else if let Some(matches) = matches.subcommand_matches("remove") {
+            recur_remove(&mut conn, matches.value_of("ID").unwrap().parse().unwrap())
+        } else if let Some(matches) = matches.subcommand_matches("apply") {
+            recur_apply(&mut conn, overlap_policy(matches))
+        } else {
+            unreachable!();
+        }
     } else if let Some(matches) = matches.subcommand_matches("weeks") {
         if matches.is_present("short") {
-            short_weeks(&mut conn, matches.value_of("PROJECT").unwrap())
+            short_weeks(&mut conn, matches.value_of("PROJECT"), matches.is_present("raw"))
+        } else {
+            weeks(&mut conn, matches.value_of("PROJECT"), matches.is_present("raw"))
+        }
+    } else if let Some(matches) = matches.subcommand_matches("off") {
+        if let Some(matches) = matches.subcommand_matches("add") {
+            off_add(&mut conn,
+                    matches.value_of("START").unwrap(),
+                    matches.value_of("until"),
+                    matches.value_of("type").unwrap_or("holiday"),
+                    matches.value_of("note"))
+        } else if matches.subcommand_matches("list").is_some() {
+            off_list(&mut conn)
+        } else if let Some(matches) = matches.subcommand_matches("remove") {
+            off_remove(&mut conn, matches.value_of("ID").unwrap().parse().unwrap())
+        } else {
+            unreachable!();
+        }
+    } else if let Some(matches) = matches.subcommand_matches("category") {
+        if let Some(matches) = matches.subcommand_matches("add") {
+            category_add(&mut conn,
+                         matches.value_of("CATEGORY").unwrap(),
+                         matches.value_of("program"),
+                         matches.value_of("title"))
+        } else if matches.subcommand_matches("list").is_some() {
+            category_list(&mut conn)
+        } else if let Some(matches) = matches.subcommand_matches("remove") {
+            category_remove(&mut conn, matches.value_of("ID").unwrap().parse().unwrap())
         } else {
-            weeks(&mut conn, matches.value_of("PROJECT").unwrap())
+            unreachable!();
+        }
+    } else if let Some(matches) = matches.subcommand_matches("privacy") {
+        if let Some(matches) = matches.subcommand_matches("add") {
+            privacy_add(&mut conn,
+                        matches.value_of("ACTION").unwrap(),
+                        matches.value_of("program"),
+                        matches.value_of("title"))
+        } else if matches.subcommand_matches("list").is_some() {
+            privacy_list(&mut conn)
+        } else if let Some(matches) = matches.subcommand_matches("remove") {
+            privacy_remove(&mut conn, matches.value_of("ID").unwrap().parse().unwrap())
+        } else {
+            unreachable!();
+        }
+    } else if let Some(matches) = matches.subcommand_matches("purge-programs") {
+        purge_programs(&mut conn, matches.value_of("since"), matches.value_of("until"))
+    } else if let Some(matches) = matches.subcommand_matches("focus") {
+        set_focus_programs(&mut conn,
+                           matches.values_of("PROGRAMS").map(|programs| programs.collect()),
+                           matches.is_present("clear"))
+    } else if let Some(matches) = matches.subcommand_matches("project-rule") {
+        if let Some(matches) = matches.subcommand_matches("add") {
+            project_rule_add(&mut conn,
+                             matches.value_of("PROJECT").unwrap(),
+                             matches.value_of("path"),
+                             matches.value_of("remote"),
+                             matches.value_of("title"))
+        } else if matches.subcommand_matches("list").is_some() {
+            project_rule_list(&mut conn)
+        } else if let Some(matches) = matches.subcommand_matches("remove") {
+            project_rule_remove(&mut conn, matches.value_of("ID").unwrap().parse().unwrap())
+        } else {
+            unreachable!();
+        }
+    } else if let Some(matches) = matches.subcommand_matches("sampling") {
+        sampling(&mut conn, matches.value_of("interval"), matches.value_of("dwell"))
+    } else if let Some(matches) = matches.subcommand_matches("group") {
+        if let Some(matches) = matches.subcommand_matches("add") {
+            group_add(&mut conn,
+                      matches.value_of("TOOL").unwrap(),
+                      &matches.values_of("PROGRAMS").unwrap().collect::<Vec<_>>())
+        } else if matches.subcommand_matches("list").is_some() {
+            group_list(&mut conn)
+        } else if let Some(matches) = matches.subcommand_matches("remove") {
+            group_remove(&mut conn, &matches.values_of("PROGRAMS").unwrap().collect::<Vec<_>>())
+        } else {
+            unreachable!();
+        }
+    } else if let Some(matches) = matches.subcommand_matches("hours") {
+        working_hours(&mut conn, matches.value_of("HOURS"))
+    } else if let Some(matches) = matches.subcommand_matches("gaps") {
+        gaps(&mut conn,
+             matches.is_present("week"),
+             matches.value_of("since"),
+             matches.value_of("until"),
+             matches.value_of("min").unwrap_or("15m"),
+             matches.is_present("assign"))
+    } else if let Some(matches) = matches.subcommand_matches("target") {
+        if let Some(matches) = matches.subcommand_matches("set") {
+            target_set(&mut conn, matches.value_of("project"), matches.value_of("HOURS").unwrap())
+        } else if let Some(matches) = matches.subcommand_matches("clear") {
+            target_clear(&mut conn, matches.value_of("project"))
+        } else if matches.subcommand_matches("list").is_some() {
+            target_list(&mut conn)
+        } else {
+            unreachable!();
+        }
+    } else if let Some(matches) = matches.subcommand_matches("rounding") {
+        if let Some(matches) = matches.subcommand_matches("set") {
+            rounding_set(&mut conn,
+                         matches.value_of("project"),
+                         matches.value_of("customer"),
+                         matches.value_of("INCREMENT").unwrap(),
+                         matches.value_of("per").unwrap_or("period"),
+                         matches.value_of("mode").unwrap_or("up"))
+        } else if let Some(matches) = matches.subcommand_matches("clear") {
+            rounding_clear(&mut conn, matches.value_of("project"), matches.value_of("customer"))
+        } else if matches.subcommand_matches("list").is_some() {
+            rounding_list(&mut conn)
+        } else {
+            unreachable!();
         }
     } else {
         unreachable!();
@@ -725,6 +4263,53 @@ fn main() {
             debug!("{:?}", e);
         },
         Err(Error::InvalidDateTime(s)) => println!("Could not parse {}", s),
+        Err(Error::InvalidDuration(s)) => println!("Could not parse duration {}", s),
         Err(Error::InactiveProject(p)) => println!("Project {} is inactive", p),
+        Err(Error::Overlap(periods)) => {
+            println!("The time period overlaps with:");
+            for (project, start, end) in periods {
+                println!("    {} {}-{}", project, start.format("%d/%m/%y %H:%M"), end.format("%H:%M"));
+            }
+            println!("Use --overlap trim-new, --overlap trim-existing or --allow-overlap to store it anyway");
+        },
+        Err(Error::PeriodNotFound(id)) => println!("Period {} not found", id),
+        Err(Error::DifferentProjects) => println!("Only periods of the same project can be merged"),
+        Err(Error::MergeWithItself(id)) => println!("Period {} can't be merged with itself", id),
+        Err(Error::EndBeforeStart(start, end)) => {
+            println!("A period can't end ({}) before it starts ({})",
+                     end.format("%d/%m/%y %H:%M"),
+                     start.format("%d/%m/%y %H:%M"))
+        }
+        Err(Error::SplitOutsidePeriod(s)) => println!("{} is not during the period", s),
+        Err(Error::InvalidEditLine(l)) => println!("Could not understand the line: {}", l),
+        Err(Error::Editor(e)) => println!("Could not edit the periods with {}", e),
+        Err(Error::InvalidSchedule(s)) => println!("Could not parse the schedule {}", s),
+        Err(Error::RecurringNotFound(id)) => println!("Recurring entry {} not found", id),
+        Err(Error::PeriodCovered) => println!("The time period is entirely covered by existing ones"),
+        Err(Error::InvalidRate(s)) => println!("{} is not a valid rate", s),
+        Err(Error::NoRate(p)) => println!("Project {} has no rate, set one with timmy rate set", p),
+        Err(Error::NothingToInvoice(c)) => println!("{} has no billable time left to invoice", c),
+        Err(Error::Io(e)) => println!("{}", e),
+        Err(Error::PeriodLocked(id)) => {
+            println!("Period {} has been invoiced. Run timmy period unlock {} to change it anyway", id, id)
+        },
+        Err(Error::InvoiceNotFound(n)) => println!("Invoice {} not found", n),
+        Err(Error::InvalidAmount(s)) => println!("{} is not a valid amount", s),
+        Err(Error::ExpenseNotFound(id)) => println!("Expense {} not found", id),
+        Err(Error::ExpenseInvoiced(id)) => println!("Expense {} has been invoiced so can't be deleted", id),
+        Err(Error::InvalidHours(s)) => println!("{} is not a valid number of hours", s),
+        Err(Error::TimeOffNotFound(id)) => println!("Time off {} not found", id),
+        Err(Error::InvalidWorkingHours(s)) => {
+            println!("Could not parse the working hours {}, use something like \"mon-fri 09:00-17:30\"", s)
+        },
+        Err(Error::InvalidPattern(s)) => println!("{} is not a valid regular expression", s),
+        Err(Error::CategoryNotFound(id)) => println!("Category rule {} not found", id),
+        Err(Error::PrivacyRuleNotFound(id)) => println!("Privacy rule {} not found", id),
+        Err(Error::ProgramNotGrouped(program)) => println!("{} isn't in a group", program),
+        Err(Error::NoProjectForDirectory) => {
+            println!("No project rule matches this directory, say which project to track or add a rule \
+                      with timmy project-rule add")
+        }
+        Err(Error::ProjectRuleNotFound(id)) => println!("Project rule {} not found", id),
     }
 }
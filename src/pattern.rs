@@ -0,0 +1,35 @@
+//! Matching windows by program and title.
+
+use regex::Regex;
+use probe::Segment;
+
+pub struct Pattern {
+    pub program: Option<String>,
+    pub title: Option<Regex>,
+}
+
+impl Pattern {
+    /// A pattern matching windows of `program` whose title matches the regex `title`. Either can be
+    /// left out, but a pattern with neither matches nothing. Returns None if the regex is invalid.
+    pub fn new(program: Option<&str>, title: Option<&str>) -> Option<Pattern> {
+        let title = match title {
+            Some(title) => Some(Regex::new(title).ok()?),
+            None => None,
+        };
+        Some(Pattern {
+            program: program.map(|program| program.into()),
+            title: title,
+        })
+    }
+
+    pub fn matches(&self, segment: &Segment) -> bool {
+        if self.program.is_none() && self.title.is_none() {
+            return false;
+        }
+        let program = self.program.as_ref().map_or(true, |program| *program == segment.program);
+        let title = self.title.as_ref().map_or(true, |regex| {
+            segment.title.as_ref().map_or(false, |title| regex.is_match(title))
+        });
+        program && title
+    }
+}
@@ -0,0 +1,52 @@
+//! Keeping private windows out of the recorded program usage.
+
+use pattern::Pattern;
+use probe::Segment;
+
+/// What the program recorded for redacted windows goes by.
+pub const PRIVATE: &'static str = "private";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    /// Leave the window out altogether
+    Ignore,
+    /// Record the time, but as "private" and without a title
+    Redact,
+}
+
+pub struct Rule {
+    pub action: Action,
+    pub pattern: Pattern,
+}
+
+/// Whether a rule matches a segment. A program recorded from inside a terminal, eg. `xterm/nvim`,
+/// is matched by the whole name or by either the terminal or the program in it.
+fn matches(rule: &Rule, segment: &Segment) -> bool {
+    if rule.pattern.matches(segment) {
+        return true;
+    }
+    if !segment.program.contains('/') {
+        return false;
+    }
+    segment.program
+        .splitn(2, '/')
+        .any(|program| rule.pattern.matches(&Segment { program: program.into(), ..segment.clone() }))
+}
+
+/// Drops the segments an ignore rule matches and redacts the ones a redact rule matches. Rules see
+/// programs by the names they are recorded under, before they are grouped into tools.
+pub fn apply(rules: &[Rule], segments: Vec<Segment>) -> Vec<Segment> {
+    let matching = |action, segment: &Segment| {
+        rules.iter().any(|rule| rule.action == action && matches(rule, segment))
+    };
+    segments.into_iter()
+        .filter(|segment| !matching(Action::Ignore, segment))
+        .map(|segment| {
+            if matching(Action::Redact, &segment) {
+                Segment { program: PRIVATE.into(), title: None, ..segment }
+            } else {
+                segment
+            }
+        })
+        .collect()
+}
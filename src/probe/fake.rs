@@ -0,0 +1,26 @@
+use std::collections::VecDeque;
+use super::{Error, Window, WindowProbe};
+
+/// Answers from a script, one answer per call, repeating the last one once the script runs out.
+pub struct Fake {
+    script: VecDeque<Option<Window>>,
+    last: Option<Window>,
+}
+
+impl Fake {
+    pub fn new(script: &[Option<Window>]) -> Fake {
+        Fake {
+            script: script.iter().cloned().collect(),
+            last: None,
+        }
+    }
+}
+
+impl WindowProbe for Fake {
+    fn current_window(&mut self) -> Result<Option<Window>, Error> {
+        if let Some(window) = self.script.pop_front() {
+            self.last = window;
+        }
+        Ok(self.last.clone())
+    }
+}
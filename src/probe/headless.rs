@@ -0,0 +1,132 @@
+//! Working out which programs were used without a display to ask, from the processes working in
+//! a directory and those in the foreground of the user's terminals.
+
+use std::collections::HashMap;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::process;
+use chrono::*;
+use super::{Segment, Tracker};
+use super::procfs::Proc;
+
+/// Splits each interval between polls among the programs working under a directory, by how much
+/// CPU time each used. When none of them did anything the interval goes to the programs in the
+/// foreground of the user's terminals instead. The segments are marked as estimated, since the
+/// order the shares are laid out in within an interval is made up.
+pub struct Headless {
+    proc_fs: Proc,
+    root: PathBuf,
+    uid: u32,
+    own_pid: u32,
+    /// The CPU time each process had used at the last poll
+    cpu: HashMap<u32, u64>,
+    since: DateTime<Local>,
+    segments: Vec<Segment>,
+}
+
+impl Headless {
+    /// Tracks the programs working under `root`, or returns None if there is no /proc to read.
+    pub fn new(root: &Path, now: DateTime<Local>) -> Option<Headless> {
+        let proc_fs = Proc::new();
+        let own_pid = process::id();
+        let uid = proc_fs.uid(own_pid)?;
+        let root = root.canonicalize().unwrap_or(root.into());
+        Some(Headless::with_proc(proc_fs, &root, uid, own_pid, now))
+    }
+
+    pub fn with_proc(proc_fs: Proc, root: &Path, uid: u32, own_pid: u32, now: DateTime<Local>) -> Headless {
+        let mut headless = Headless {
+            proc_fs: proc_fs,
+            root: root.into(),
+            uid: uid,
+            own_pid: own_pid,
+            cpu: HashMap::new(),
+            since: now,
+            segments: vec![],
+        };
+        headless.sample();
+        headless
+    }
+
+    /// The CPU time each program working under the root used since the last sample, most first,
+    /// and the programs in the foreground of the user's terminals.
+    fn sample(&mut self) -> (Vec<(String, u64)>, Vec<String>) {
+        let mut used: Vec<(String, u64)> = vec![];
+        let mut foreground: Vec<String> = vec![];
+        let mut cpu = HashMap::new();
+        for pid in self.proc_fs.pids() {
+            if pid == self.own_pid || self.proc_fs.uid(pid) != Some(self.uid) {
+                continue;
+            }
+            let process = match self.proc_fs.process(pid) {
+                Some(process) => process,
+                None => continue,
+            };
+            if process.tty_nr != 0 && process.tpgid == pid as i32 && !foreground.contains(&process.comm) {
+                foreground.push(process.comm.clone());
+            }
+            if !self.proc_fs.cwd(pid).map_or(false, |cwd| cwd.starts_with(&self.root)) {
+                continue;
+            }
+            cpu.insert(pid, process.cpu);
+            // a process seen for the first time has nothing to compare against
+            let delta = process.cpu - self.cpu.get(&pid).map_or(process.cpu, |&last| last.min(process.cpu));
+            if delta == 0 {
+                continue;
+            }
+            match used.iter().position(|&(ref program, _)| *program == process.comm) {
+                Some(i) => used[i].1 += delta,
+                None => used.push((process.comm, delta)),
+            }
+        }
+        self.cpu = cpu;
+        used.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        foreground.sort();
+        (used, foreground)
+    }
+
+    fn push(&mut self, program: String, start: DateTime<Local>, end: DateTime<Local>) {
+        if start >= end {
+            return;
+        }
+        if let Some(last) = self.segments.last_mut() {
+            if last.program == program && last.end == start {
+                last.end = end;
+                return;
+            }
+        }
+        self.segments.push(Segment {
+            program: program,
+            title: None,
+            start: start,
+            end: end,
+            estimated: true,
+        });
+    }
+}
+
+impl Tracker for Headless {
+    fn poll(&mut self, now: DateTime<Local>) {
+        let (used, foreground) = self.sample();
+        let shares = if used.is_empty() {
+            foreground.into_iter().map(|program| (program, 1)).collect()
+        } else {
+            used
+        };
+        let total: u64 = shares.iter().map(|&(_, share)| share).sum();
+        let interval = (now - self.since).num_milliseconds();
+        let (since, mut start, mut done) = (self.since, self.since, 0);
+        for (program, share) in shares {
+            done += share;
+            let end = since + Duration::milliseconds(interval * done as i64 / total as i64);
+            self.push(program, start, end);
+            start = end;
+        }
+        self.since = now;
+    }
+
+    fn finish(&mut self, now: DateTime<Local>) -> Vec<Segment> {
+        self.poll(now);
+        mem::replace(&mut self.segments, vec![])
+    }
+}
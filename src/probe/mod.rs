@@ -0,0 +1,244 @@
+//! Finding out which window has focus, and recording when each one had it.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::mem;
+use chrono::*;
+
+mod json;
+pub mod x11;
+pub mod sway;
+pub mod fake;
+pub mod procfs;
+pub mod terminal;
+pub mod headless;
+
+pub use self::x11::X11;
+pub use self::sway::Sway;
+pub use self::fake::Fake;
+pub use self::terminal::Terminals;
+pub use self::headless::Headless;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// The backend answered with something we couldn't make sense of.
+    Unexpected(String),
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+/// The focused window: the name of the program owning it, its title and the pid of its process,
+/// when they are known.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Window {
+    pub program: String,
+    pub title: Option<String>,
+    pub pid: Option<u32>,
+}
+
+impl Window {
+    pub fn new(program: &str, title: Option<&str>) -> Window {
+        Window {
+            program: program.into(),
+            title: title.map(|title| title.into()),
+            pid: None,
+        }
+    }
+}
+
+pub trait WindowProbe {
+    /// The focused window, or None if nothing has focus.
+    fn current_window(&mut self) -> Result<Option<Window>, Error>;
+}
+
+impl<P: WindowProbe + ?Sized> WindowProbe for Box<P> {
+    fn current_window(&mut self) -> Result<Option<Window>, Error> {
+        (**self).current_window()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backend {
+    X11,
+    Sway,
+}
+
+/// Works out which backend suits the session described by `var`, which looks up an environment
+/// variable. Other Wayland compositors don't tell clients which window has focus, so there is
+/// nothing to use there even if Xwayland has set DISPLAY.
+pub fn detect<F: Fn(&str) -> Option<String>>(var: F) -> Option<Backend> {
+    let set = |name| var(name).map_or(false, |value| !value.is_empty());
+    if set("SWAYSOCK") {
+        Some(Backend::Sway)
+    } else if set("WAYLAND_DISPLAY") {
+        None
+    } else if set("DISPLAY") {
+        Some(Backend::X11)
+    } else {
+        None
+    }
+}
+
+/// Opens the backend for the current session, if there is one and it works, looking inside
+/// terminals for the program in the foreground.
+pub fn from_env() -> Option<Box<WindowProbe + Send>> {
+    let probe: Box<WindowProbe + Send> = match detect(|name| ::std::env::var(name).ok()) {
+        Some(Backend::X11) => Box::new(Terminals::new(X11::new().ok()?)),
+        Some(Backend::Sway) => Box::new(Terminals::new(Sway::new().ok()?)),
+        None => return None,
+    };
+    Some(probe)
+}
+
+/// The command name of a process, or None if it has already exited.
+fn process_name(pid: u32) -> Result<Option<String>, Error> {
+    let mut name = String::new();
+    match File::open(format!("/proc/{}/comm", pid)) {
+        Ok(mut f) => f.read_to_string(&mut name)?,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let name = name.trim();
+    Ok(if name.is_empty() { None } else { Some(name.to_string()) })
+}
+
+/// A stretch of time during which one window had focus.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    pub program: String,
+    pub title: Option<String>,
+    pub start: DateTime<Local>,
+    pub end: DateTime<Local>,
+    /// Worked out from CPU time rather than seen to have focus, so it shows how long a program was
+    /// used for but not when
+    pub estimated: bool,
+}
+
+impl Segment {
+    /// The part of the segment between `start` and `end`, if any of it is.
+    pub fn clip(&self, start: DateTime<Local>, end: DateTime<Local>) -> Option<Segment> {
+        let start = if self.start > start { self.start } else { start };
+        let end = if self.end < end { self.end } else { end };
+        if start >= end {
+            return None;
+        }
+        Some(Segment {
+            program: self.program.clone(),
+            title: self.title.clone(),
+            start: start,
+            end: end,
+            estimated: self.estimated,
+        })
+    }
+}
+
+/// Folds segments shorter than `min` into the one before, so flicking through windows on the way to
+/// another isn't recorded. A short first segment goes to the one after it instead. Segments less than
+/// a second apart count as next to each other, and ones left next to each other for the same window
+/// are joined. Estimated segments are left as they are, as their order means nothing.
+pub fn settle(segments: Vec<Segment>, min: Duration) -> Vec<Segment> {
+    let follows = |before: &Segment, after: &Segment| {
+        !before.estimated && !after.estimated && after.start - before.end < Duration::seconds(1)
+    };
+    let mut settled: Vec<Segment> = vec![];
+    for segment in segments {
+        if let Some(last) = settled.last_mut() {
+            let same = last.program == segment.program && last.title == segment.title;
+            if follows(last, &segment) && (same || segment.end - segment.start < min) {
+                last.end = segment.end;
+                continue;
+            }
+        }
+        settled.push(segment);
+    }
+    if settled.len() > 1 && settled[0].end - settled[0].start < min && follows(&settled[0], &settled[1]) {
+        settled[1].start = settled[0].start;
+        settled.remove(0);
+    }
+    settled
+}
+
+/// The seconds spent in each program, most used first.
+pub fn totals(segments: &[Segment]) -> Vec<(String, i64)> {
+    let mut times: HashMap<&str, Duration> = HashMap::new();
+    for segment in segments {
+        let time = times.entry(&segment.program).or_insert_with(Duration::zero);
+        *time = *time + (segment.end - segment.start);
+    }
+    let mut totals: Vec<(String, i64)> =
+        times.into_iter().map(|(program, time)| (program.into(), time.num_seconds())).collect();
+    totals.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    totals
+}
+
+/// Records what was being used each time it is polled.
+pub trait Tracker {
+    fn poll(&mut self, now: DateTime<Local>);
+
+    /// Stops tracking, returning the segments in the order they happened.
+    fn finish(&mut self, now: DateTime<Local>) -> Vec<Segment>;
+
+    /// The window that had focus when last polled, for trackers that know.
+    fn current(&self) -> Option<&Window> {
+        None
+    }
+}
+
+/// Polls a probe and records which window had focus when.
+pub struct ProgramTracker<P> {
+    probe: P,
+    current: Option<Window>,
+    since: DateTime<Local>,
+    segments: Vec<Segment>,
+}
+
+impl<P: WindowProbe> ProgramTracker<P> {
+    pub fn new(mut probe: P, now: DateTime<Local>) -> ProgramTracker<P> {
+        let current = probe.current_window().unwrap_or(None);
+        ProgramTracker {
+            probe: probe,
+            current: current,
+            since: now,
+            segments: vec![],
+        }
+    }
+
+    fn end_segment(&mut self, now: DateTime<Local>) {
+        if let Some(ref window) = self.current {
+            self.segments.push(Segment {
+                program: window.program.clone(),
+                title: window.title.clone(),
+                start: self.since,
+                end: now,
+                estimated: false,
+            });
+        }
+        self.since = now;
+    }
+}
+
+impl<P: WindowProbe> Tracker for ProgramTracker<P> {
+    /// Asks the probe what has focus now. A probe that fails counts as nothing having focus.
+    fn poll(&mut self, now: DateTime<Local>) {
+        let window = self.probe.current_window().unwrap_or(None);
+        if window != self.current {
+            self.end_segment(now);
+            self.current = window;
+        }
+    }
+
+    fn finish(&mut self, now: DateTime<Local>) -> Vec<Segment> {
+        self.end_segment(now);
+        mem::replace(&mut self.segments, vec![])
+    }
+
+    fn current(&self) -> Option<&Window> {
+        self.current.as_ref()
+    }
+}
@@ -0,0 +1,101 @@
+//! Reading processes out of `/proc`.
+
+use std::fs::{self, File};
+use std::io::Read;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+/// The parts of `/proc/<pid>/stat` we need.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Process {
+    pub pid: u32,
+    pub comm: String,
+    pub ppid: u32,
+    /// The terminal the process is attached to, or 0 if it isn't.
+    pub tty_nr: i32,
+    /// The foreground process group of the process's terminal, or -1 if it hasn't got one.
+    pub tpgid: i32,
+    /// User and system CPU time, in clock ticks.
+    pub cpu: u64,
+}
+
+pub fn parse_stat(s: &str) -> Option<Process> {
+    // the name is in brackets and can contain spaces and brackets itself
+    let open = s.find('(')?;
+    let close = s.rfind(')')?;
+    let fields: Vec<&str> = s[close + 1..].split_whitespace().collect();
+    if fields.len() < 13 {
+        return None;
+    }
+    Some(Process {
+        pid: s[..open].trim().parse().ok()?,
+        comm: s[open + 1..close].into(),
+        ppid: fields[1].parse().ok()?,
+        tty_nr: fields[4].parse().ok()?,
+        tpgid: fields[5].parse().ok()?,
+        cpu: fields[11].parse::<u64>().ok()? + fields[12].parse::<u64>().ok()?,
+    })
+}
+
+/// Reads processes out of a proc filesystem.
+pub struct Proc {
+    root: PathBuf,
+}
+
+impl Proc {
+    pub fn new() -> Proc {
+        Proc::at("/proc")
+    }
+
+    pub fn at<P: AsRef<Path>>(root: P) -> Proc {
+        Proc { root: root.as_ref().into() }
+    }
+
+    /// Every process there is.
+    pub fn pids(&self) -> Vec<u32> {
+        let entries = match fs::read_dir(&self.root) {
+            Ok(entries) => entries,
+            Err(_) => return vec![],
+        };
+        let mut pids: Vec<u32> = entries.filter_map(|entry| entry.ok())
+            .filter_map(|entry| entry.file_name().to_str().and_then(|name| name.parse().ok()))
+            .collect();
+        pids.sort();
+        pids
+    }
+
+    pub fn process(&self, pid: u32) -> Option<Process> {
+        let mut stat = String::new();
+        File::open(self.root.join(pid.to_string()).join("stat")).ok()?.read_to_string(&mut stat).ok()?;
+        parse_stat(&stat)
+    }
+
+    pub fn children(&self, pid: u32) -> Vec<u32> {
+        let path = self.root.join(pid.to_string()).join("task").join(pid.to_string()).join("children");
+        let mut children = String::new();
+        if File::open(path).and_then(|mut f| f.read_to_string(&mut children)).is_ok() {
+            return children.split_whitespace().filter_map(|pid| pid.parse().ok()).collect();
+        }
+        // older kernels don't list children, so look for processes naming this one as their parent
+        self.pids()
+            .into_iter()
+            .filter(|&child| self.process(child).map_or(false, |process| process.ppid == pid))
+            .collect()
+    }
+
+    /// The terminal device a process reads from.
+    pub fn tty(&self, pid: u32) -> Option<PathBuf> {
+        let tty = fs::read_link(self.root.join(pid.to_string()).join("fd").join("0")).ok()?;
+        if tty.starts_with("/dev/pts") || tty.starts_with("/dev/tty") { Some(tty) } else { None }
+    }
+
+    /// The directory a process is working in.
+    pub fn cwd(&self, pid: u32) -> Option<PathBuf> {
+        fs::read_link(self.root.join(pid.to_string()).join("cwd")).ok()
+    }
+
+    /// The user a process runs as.
+    pub fn uid(&self, pid: u32) -> Option<u32> {
+        fs::metadata(self.root.join(pid.to_string())).ok().map(|metadata| metadata.uid())
+    }
+}
@@ -0,0 +1,48 @@
+use std::process::{Command, Stdio};
+use super::json::{self, Value};
+use super::{Error, Window, WindowProbe, process_name};
+
+/// Asks sway through swaymsg.
+pub struct Sway;
+
+impl Sway {
+    pub fn new() -> Result<Sway, Error> {
+        let status = Command::new("swaymsg").args(&["-t", "get_version"]).stdout(Stdio::null()).status()?;
+        if !status.success() {
+            return Err(Error::Unexpected("swaymsg couldn't talk to sway".into()));
+        }
+        Ok(Sway)
+    }
+}
+
+fn focused(node: &Value) -> Option<&Value> {
+    if node.get("focused").and_then(Value::as_bool) == Some(true) {
+        return Some(node);
+    }
+    let children = node.get("nodes").and_then(Value::as_array).unwrap_or(&[]);
+    let floating = node.get("floating_nodes").and_then(Value::as_array).unwrap_or(&[]);
+    children.iter().chain(floating).filter_map(focused).next()
+}
+
+/// Finds the pid and title of the focused window in the output of `swaymsg -t get_tree`. A
+/// focused workspace or output has no pid, which means no window has focus.
+pub fn focused_window(tree: &str) -> Result<Option<(u32, Option<String>)>, Error> {
+    let tree = json::parse(tree).ok_or_else(|| Error::Unexpected("swaymsg printed invalid JSON".into()))?;
+    let node = match focused(&tree) {
+        Some(node) => node,
+        None => return Ok(None),
+    };
+    let title = node.get("name").and_then(Value::as_str).map(|title| title.to_string());
+    Ok(node.get("pid").and_then(Value::as_f64).map(|pid| (pid as u32, title)))
+}
+
+impl WindowProbe for Sway {
+    fn current_window(&mut self) -> Result<Option<Window>, Error> {
+        let output = Command::new("swaymsg").args(&["-t", "get_tree"]).output()?;
+        let (pid, title) = match focused_window(&String::from_utf8_lossy(&output.stdout))? {
+            Some(window) => window,
+            None => return Ok(None),
+        };
+        Ok(process_name(pid)?.map(|program| Window { program: program, title: title, pid: Some(pid) }))
+    }
+}
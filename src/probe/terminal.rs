@@ -0,0 +1,141 @@
+//! Looking inside terminal windows for the program in the foreground.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, Instant, SystemTime};
+use super::{Error, Window, WindowProbe};
+use super::procfs::{Proc, Process};
+
+/// Programs whose windows are terminals, by the name the process goes by.
+pub const TERMINALS: &'static [&'static str] = &["gnome-terminal-", "konsole", "xterm", "uxterm", "urxvt",
+                                                  "rxvt", "alacritty", "kitty", "foot", "st", "terminator",
+                                                  "tilix", "xfce4-terminal", "mate-terminal", "lxterminal",
+                                                  "qterminal", "sakura", "terminology", "wezterm-gui"];
+
+/// How long what is in the foreground of a terminal is trusted for while its tty is left alone.
+const REFRESH: Duration = Duration::from_secs(1);
+
+/// When a tty was last written to.
+fn last_used(tty: &Path) -> Option<SystemTime> {
+    fs::metadata(tty).and_then(|metadata| metadata.modified()).ok()
+}
+
+impl Proc {
+    /// The foreground process in the most recently used of the terminals held by the children of
+    /// `pid`, along with the terminal it is in.
+    pub fn foreground(&self, pid: u32) -> Option<(Process, PathBuf)> {
+        let mut latest = None;
+        for child in self.children(pid) {
+            let tty = match self.tty(child) {
+                Some(tty) => tty,
+                None => continue,
+            };
+            let used = last_used(&tty);
+            if latest.as_ref().map_or(true, |&(_, _, ref latest_used)| used > *latest_used) {
+                latest = Some((child, tty, used));
+            }
+        }
+        let (child, tty, _) = latest?;
+        let shell = self.process(child)?;
+        let foreground = if shell.tpgid > 0 { self.process(shell.tpgid as u32) } else { None };
+        Some((foreground.unwrap_or(shell), tty))
+    }
+
+    /// The program in the foreground of a tmux or screen client, if `client` is one.
+    fn multiplexed(&self, client: &Process, tty: &Path) -> Option<String> {
+        if client.comm.starts_with("tmux") {
+            let output = Command::new("tmux")
+                .args(&["display-message", "-p", "-c"])
+                .arg(tty)
+                .arg("#{pane_current_command}")
+                .output()
+                .ok()?;
+            let command = String::from_utf8_lossy(&output.stdout).trim().to_string();
+            if output.status.success() && !command.is_empty() { Some(command) } else { None }
+        } else if client.comm == "screen" {
+            // a screen started from this client runs its windows under a SCREEN child
+            let server = self.children(client.pid)
+                .into_iter()
+                .find(|&pid| self.process(pid).map_or(false, |process| process.comm == "SCREEN"))?;
+            self.foreground(server).map(|(process, _)| process.comm)
+        } else {
+            None
+        }
+    }
+}
+
+/// What was found in the foreground of a terminal window, and when.
+struct Lookup {
+    terminal: String,
+    pid: u32,
+    program: String,
+    tty: Option<PathBuf>,
+    used: Option<SystemTime>,
+    at: Instant,
+}
+
+/// Wraps another probe, replacing a focused terminal with `terminal/program` for the program in
+/// its foreground. Looking that up means reading through /proc and maybe running tmux, so it is
+/// only done again for the same window once a second or when its tty is used.
+pub struct Terminals<P> {
+    probe: P,
+    proc_fs: Proc,
+    last: Option<Lookup>,
+}
+
+impl<P: WindowProbe> Terminals<P> {
+    pub fn new(probe: P) -> Terminals<P> {
+        Terminals::with_proc(probe, Proc::new())
+    }
+
+    pub fn with_proc(probe: P, proc_fs: Proc) -> Terminals<P> {
+        Terminals {
+            probe: probe,
+            proc_fs: proc_fs,
+            last: None,
+        }
+    }
+}
+
+impl<P: WindowProbe> WindowProbe for Terminals<P> {
+    fn current_window(&mut self) -> Result<Option<Window>, Error> {
+        let mut window = match self.probe.current_window()? {
+            Some(window) => window,
+            None => return Ok(None),
+        };
+        if !TERMINALS.contains(&&window.program[..]) {
+            return Ok(Some(window));
+        }
+        let pid = match window.pid {
+            Some(pid) => pid,
+            None => return Ok(Some(window)),
+        };
+        let fresh = match self.last {
+            Some(ref last) => {
+                last.terminal == window.program && last.pid == pid && last.at.elapsed() < REFRESH &&
+                last.tty.as_ref().and_then(|tty| last_used(tty)) == last.used
+            }
+            None => false,
+        };
+        if !fresh {
+            let (program, tty) = match self.proc_fs.foreground(pid) {
+                Some((process, tty)) => {
+                    let program = self.proc_fs.multiplexed(&process, &tty).unwrap_or(process.comm);
+                    (format!("{}/{}", window.program, program), Some(tty))
+                }
+                None => (window.program.clone(), None),
+            };
+            self.last = Some(Lookup {
+                terminal: window.program.clone(),
+                pid: pid,
+                program: program,
+                used: tty.as_ref().and_then(|tty| last_used(tty)),
+                tty: tty,
+                at: Instant::now(),
+            });
+        }
+        window.program = self.last.as_ref().unwrap().program.clone();
+        Ok(Some(window))
+    }
+}
@@ -0,0 +1,476 @@
+//! Just enough of the X11 protocol to follow `_NET_ACTIVE_WINDOW` on the root window and the
+//! title of the window it points to.
+
+use std::env;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs};
+use std::os::unix::net::UnixStream;
+use super::{Error, Window, WindowProbe, process_name};
+
+const CHANGE_WINDOW_ATTRIBUTES: u8 = 2;
+const INTERN_ATOM: u8 = 16;
+const GET_PROPERTY: u8 = 20;
+const PROPERTY_NOTIFY: u8 = 28;
+const WM_NAME: u32 = 39;
+/// How much of a title to ask for, in 4 byte units.
+const TITLE_LENGTH: u32 = 256;
+const CW_EVENT_MASK: u32 = 1 << 11;
+const PROPERTY_CHANGE_MASK: u32 = 1 << 22;
+const FAMILY_INTERNET: u16 = 0;
+const FAMILY_INTERNET6: u16 = 6;
+const FAMILY_LOCAL: u16 = 256;
+const FAMILY_WILD: u16 = 65535;
+const MIT_MAGIC_COOKIE: &'static str = "MIT-MAGIC-COOKIE-1";
+
+/// Where a `DISPLAY` like `:0`, `unix:1.0` or `remote:10` points.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Display {
+    pub host: Option<String>,
+    pub number: u32,
+}
+
+pub fn parse_display(s: &str) -> Option<Display> {
+    let colon = s.rfind(':')?;
+    let host = &s[..colon];
+    let number = s[colon + 1..].split('.').next()?.parse().ok()?;
+    Some(Display {
+        host: if host.is_empty() || host == "unix" { None } else { Some(host.to_string()) },
+        number: number,
+    })
+}
+
+/// One entry in an Xauthority file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuthEntry {
+    pub family: u16,
+    pub address: Vec<u8>,
+    pub number: String,
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+fn be_u16(bytes: &[u8]) -> Option<u16> {
+    if bytes.len() < 2 {
+        return None;
+    }
+    Some((bytes[0] as u16) << 8 | bytes[1] as u16)
+}
+
+/// Parses an Xauthority file, stopping at the first entry that is cut short.
+pub fn parse_xauthority(mut bytes: &[u8]) -> Vec<AuthEntry> {
+    fn field(bytes: &mut &[u8]) -> Option<Vec<u8>> {
+        let len = be_u16(bytes)? as usize;
+        if bytes.len() < 2 + len {
+            return None;
+        }
+        let value = bytes[2..2 + len].to_vec();
+        *bytes = &bytes[2 + len..];
+        Some(value)
+    }
+    fn entry(bytes: &mut &[u8]) -> Option<AuthEntry> {
+        let family = be_u16(bytes)?;
+        *bytes = &bytes[2..];
+        Some(AuthEntry {
+            family: family,
+            address: field(bytes)?,
+            number: String::from_utf8_lossy(&field(bytes)?).into_owned(),
+            name: String::from_utf8_lossy(&field(bytes)?).into_owned(),
+            data: field(bytes)?,
+        })
+    }
+    let mut entries = vec![];
+    while let Some(e) = entry(&mut bytes) {
+        entries.push(e);
+    }
+    entries
+}
+
+/// Picks the cookie for a display, preferring an entry for its host over one for any host. A local
+/// display has no `addresses`; a remote one is reached at `addresses`, and is really on this host
+/// when one of them is a loopback address, as with the `localhost:10` that `ssh -X` sets up.
+pub fn find_cookie<'a>(entries: &'a [AuthEntry],
+                       number: u32,
+                       hostname: &str,
+                       addresses: &[IpAddr])
+                       -> Option<&'a AuthEntry> {
+    let number = number.to_string();
+    let local = addresses.is_empty() || addresses.iter().any(|a| a.is_loopback());
+    let usable = |e: &&AuthEntry| e.name == MIT_MAGIC_COOKIE && (e.number.is_empty() || e.number == number);
+    let for_host = |e: &&AuthEntry| {
+        match e.family {
+            FAMILY_LOCAL => local && e.address == hostname.as_bytes(),
+            FAMILY_INTERNET => addresses.iter().any(|a| match *a {
+                IpAddr::V4(ref a) => e.address == a.octets(),
+                _ => false,
+            }),
+            FAMILY_INTERNET6 => addresses.iter().any(|a| match *a {
+                IpAddr::V6(ref a) => e.address == a.octets(),
+                _ => false,
+            }),
+            _ => false,
+        }
+    };
+    entries.iter()
+        .filter(&usable)
+        .find(&for_host)
+        .or_else(|| entries.iter().filter(&usable).find(|e| e.family == FAMILY_WILD))
+}
+
+fn xauthority() -> Vec<AuthEntry> {
+    let path = match env::var("XAUTHORITY") {
+        Ok(path) => path,
+        Err(_) => match env::var("HOME") {
+            Ok(home) => format!("{}/.Xauthority", home),
+            Err(_) => return vec![],
+        },
+    };
+    let mut bytes = vec![];
+    match File::open(path).and_then(|mut f| f.read_to_end(&mut bytes)) {
+        Ok(_) => parse_xauthority(&bytes),
+        Err(_) => vec![],
+    }
+}
+
+fn hostname() -> String {
+    let mut name = String::new();
+    let _ = File::open("/proc/sys/kernel/hostname").and_then(|mut f| f.read_to_string(&mut name));
+    name.trim().to_string()
+}
+
+fn u16_at(bytes: &[u8], i: usize) -> u16 {
+    bytes[i] as u16 | (bytes[i + 1] as u16) << 8
+}
+
+fn u32_at(bytes: &[u8], i: usize) -> u32 {
+    u16_at(bytes, i) as u32 | (u16_at(bytes, i + 2) as u32) << 16
+}
+
+fn push_u16(bytes: &mut Vec<u8>, n: u16) {
+    bytes.extend_from_slice(&[n as u8, (n >> 8) as u8]);
+}
+
+fn push_u32(bytes: &mut Vec<u8>, n: u32) {
+    push_u16(bytes, n as u16);
+    push_u16(bytes, (n >> 16) as u16);
+}
+
+fn push_padded(bytes: &mut Vec<u8>, data: &[u8]) {
+    bytes.extend_from_slice(data);
+    while bytes.len() % 4 != 0 {
+        bytes.push(0);
+    }
+}
+
+fn padded(n: usize) -> usize {
+    (n + 3) / 4 * 4
+}
+
+enum Stream {
+    Unix(UnixStream),
+    Tcp(TcpStream),
+}
+
+impl Stream {
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        match *self {
+            Stream::Unix(ref s) => s.set_nonblocking(nonblocking),
+            Stream::Tcp(ref s) => s.set_nonblocking(nonblocking),
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match *self {
+            Stream::Unix(ref mut s) => s.read(buf),
+            Stream::Tcp(ref mut s) => s.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match *self {
+            Stream::Unix(ref mut s) => s.write(buf),
+            Stream::Tcp(ref mut s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match *self {
+            Stream::Unix(ref mut s) => s.flush(),
+            Stream::Tcp(ref mut s) => s.flush(),
+        }
+    }
+}
+
+/// Talks to the X server directly. The answer is kept until a PropertyNotify says the active
+/// window or its title has changed, so most calls don't need a round trip.
+pub struct X11 {
+    stream: Stream,
+    nonblocking: bool,
+    buf: Vec<u8>,
+    sequence: u16,
+    root: u32,
+    active_window_atom: u32,
+    wm_pid_atom: u32,
+    wm_name_atom: u32,
+    window: u32,
+    stale: bool,
+    current: Option<Window>,
+}
+
+impl X11 {
+    pub fn new() -> Result<X11, Error> {
+        let display = env::var("DISPLAY")
+            .ok()
+            .and_then(|d| parse_display(&d))
+            .ok_or_else(|| Error::Unexpected("DISPLAY isn't set".into()))?;
+        let mut addresses: Vec<SocketAddr> = vec![];
+        let stream = match display.host {
+            None => Stream::Unix(UnixStream::connect(format!("/tmp/.X11-unix/X{}", display.number))?),
+            Some(ref host) => {
+                let port = match display.number.checked_add(6000) {
+                    Some(port) if port <= u16::max_value() as u32 => port as u16,
+                    _ => return Err(Error::Unexpected(format!("display number {} is too large", display.number))),
+                };
+                addresses = (host.as_str(), port).to_socket_addrs()?.collect();
+                Stream::Tcp(TcpStream::connect(&addresses[..])?)
+            }
+        };
+        let mut x = X11 {
+            stream: stream,
+            nonblocking: false,
+            buf: vec![],
+            sequence: 0,
+            root: 0,
+            active_window_atom: 0,
+            wm_pid_atom: 0,
+            wm_name_atom: 0,
+            window: 0,
+            stale: true,
+            current: None,
+        };
+        let entries = xauthority();
+        let addresses: Vec<IpAddr> = addresses.iter().map(|a| a.ip()).collect();
+        let cookie = find_cookie(&entries, display.number, &hostname(), &addresses);
+        x.setup(cookie)?;
+        x.active_window_atom = x.intern_atom("_NET_ACTIVE_WINDOW")?;
+        x.wm_pid_atom = x.intern_atom("_NET_WM_PID")?;
+        x.wm_name_atom = x.intern_atom("_NET_WM_NAME")?;
+        let root = x.root;
+        x.watch(root)?;
+        Ok(x)
+    }
+
+    /// Asks for PropertyNotify events about a window.
+    fn watch(&mut self, window: u32) -> Result<(), Error> {
+        let mut request = vec![CHANGE_WINDOW_ATTRIBUTES, 0];
+        push_u16(&mut request, 4);
+        push_u32(&mut request, window);
+        push_u32(&mut request, CW_EVENT_MASK);
+        push_u32(&mut request, PROPERTY_CHANGE_MASK);
+        self.send(&request)?;
+        Ok(())
+    }
+
+    fn setup(&mut self, cookie: Option<&AuthEntry>) -> Result<(), Error> {
+        let (name, data) = match cookie {
+            Some(entry) => (entry.name.as_bytes(), &entry.data[..]),
+            None => (&b""[..], &b""[..]),
+        };
+        let mut request = vec![b'l', 0];
+        push_u16(&mut request, 11);
+        push_u16(&mut request, 0);
+        push_u16(&mut request, name.len() as u16);
+        push_u16(&mut request, data.len() as u16);
+        push_u16(&mut request, 0);
+        push_padded(&mut request, name);
+        push_padded(&mut request, data);
+        self.stream.write_all(&request)?;
+        self.fill(8)?;
+        let len = 8 + 4 * u16_at(&self.buf, 6) as usize;
+        self.fill(len)?;
+        let reply: Vec<u8> = self.buf.drain(..len).collect();
+        if reply[0] != 1 {
+            let reason = reply.get(8..8 + reply[1] as usize).unwrap_or(&[]);
+            return Err(Error::Unexpected(format!("the X server refused the connection: {}",
+                                                 String::from_utf8_lossy(reason))));
+        }
+        let vendor_len = u16_at(&reply, 24) as usize;
+        let formats = reply[29] as usize;
+        let screen = 40 + padded(vendor_len) + 8 * formats;
+        if reply.len() < screen + 4 {
+            return Err(Error::Unexpected("the X server didn't describe any screens".into()));
+        }
+        self.root = u32_at(&reply, screen);
+        Ok(())
+    }
+
+    fn set_nonblocking(&mut self, nonblocking: bool) -> Result<(), Error> {
+        if self.nonblocking != nonblocking {
+            self.stream.set_nonblocking(nonblocking)?;
+            self.nonblocking = nonblocking;
+        }
+        Ok(())
+    }
+
+    /// Reads whatever is available into the buffer, returning false if nothing was.
+    fn read_more(&mut self) -> Result<bool, Error> {
+        let mut chunk = [0; 4096];
+        match self.stream.read(&mut chunk) {
+            Ok(0) => Err(Error::Unexpected("the X server hung up".into())),
+            Ok(n) => {
+                self.buf.extend_from_slice(&chunk[..n]);
+                Ok(true)
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn fill(&mut self, len: usize) -> Result<(), Error> {
+        self.set_nonblocking(false)?;
+        while self.buf.len() < len {
+            self.read_more()?;
+        }
+        Ok(())
+    }
+
+    /// The next reply, error or event. Returns None if `wait` is false and none has arrived.
+    fn packet(&mut self, wait: bool) -> Result<Option<Vec<u8>>, Error> {
+        self.set_nonblocking(!wait)?;
+        loop {
+            if self.buf.len() >= 32 {
+                let len = if self.buf[0] == 1 { 32 + 4 * u32_at(&self.buf, 4) as usize } else { 32 };
+                if self.buf.len() >= len {
+                    return Ok(Some(self.buf.drain(..len).collect()));
+                }
+            }
+            if !self.read_more()? {
+                return Ok(None);
+            }
+        }
+    }
+
+    fn handle_event(&mut self, packet: &[u8]) {
+        if packet[0] & 0x7f != PROPERTY_NOTIFY {
+            return;
+        }
+        let (window, atom) = (u32_at(packet, 4), u32_at(packet, 8));
+        if (window == self.root && atom == self.active_window_atom) ||
+           (window == self.window && (atom == self.wm_name_atom || atom == WM_NAME)) {
+            self.stale = true;
+        }
+    }
+
+    fn send(&mut self, request: &[u8]) -> Result<u16, Error> {
+        self.set_nonblocking(false)?;
+        self.stream.write_all(request)?;
+        self.sequence = self.sequence.wrapping_add(1);
+        Ok(self.sequence)
+    }
+
+    /// Sends a request and waits for its reply. None means the server answered with an error, as
+    /// it does when asked about a window that has just been closed.
+    fn request(&mut self, request: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        let sequence = self.send(request)?;
+        loop {
+            let packet = match self.packet(true)? {
+                Some(packet) => packet,
+                None => continue,
+            };
+            match packet[0] {
+                0 | 1 if u16_at(&packet, 2) != sequence => {}
+                0 => return Ok(None),
+                1 => return Ok(Some(packet)),
+                _ => self.handle_event(&packet),
+            }
+        }
+    }
+
+    fn intern_atom(&mut self, name: &str) -> Result<u32, Error> {
+        let mut request = vec![INTERN_ATOM, 0];
+        push_u16(&mut request, 2 + (padded(name.len()) / 4) as u16);
+        push_u16(&mut request, name.len() as u16);
+        push_u16(&mut request, 0);
+        push_padded(&mut request, name.as_bytes());
+        match self.request(&request)? {
+            Some(reply) => Ok(u32_at(&reply, 8)),
+            None => Err(Error::Unexpected(format!("couldn't look up the {} atom", name))),
+        }
+    }
+
+    /// The format and the first `length` 4 byte units of a property, if the window has it.
+    fn property(&mut self, window: u32, atom: u32, length: u32) -> Result<Option<(u8, Vec<u8>)>, Error> {
+        let mut request = vec![GET_PROPERTY, 0];
+        push_u16(&mut request, 6);
+        push_u32(&mut request, window);
+        push_u32(&mut request, atom);
+        push_u32(&mut request, 0);
+        push_u32(&mut request, 0);
+        push_u32(&mut request, length);
+        Ok(self.request(&request)?.and_then(|reply| {
+            let format = reply[1];
+            let len = u32_at(&reply, 16) as usize * format as usize / 8;
+            if format == 0 || reply.len() < 32 + len {
+                return None;
+            }
+            Some((format, reply[32..32 + len].to_vec()))
+        }))
+    }
+
+    /// The first 32 bit value of a property, if the window has it.
+    fn cardinal(&mut self, window: u32, atom: u32) -> Result<Option<u32>, Error> {
+        Ok(match self.property(window, atom, 1)? {
+            Some((32, ref value)) if value.len() >= 4 => Some(u32_at(value, 0)),
+            _ => None,
+        })
+    }
+
+    fn title(&mut self, window: u32) -> Result<Option<String>, Error> {
+        let wm_name_atom = self.wm_name_atom;
+        let title = match self.property(window, wm_name_atom, TITLE_LENGTH)? {
+            Some((8, title)) => Some(title),
+            _ => self.property(window, WM_NAME, TITLE_LENGTH)?.and_then(|(format, title)| {
+                if format == 8 { Some(title) } else { None }
+            }),
+        };
+        Ok(title.map(|title| String::from_utf8_lossy(&title).into_owned()))
+    }
+}
+
+impl WindowProbe for X11 {
+    fn current_window(&mut self) -> Result<Option<Window>, Error> {
+        while let Some(packet) = self.packet(false)? {
+            self.handle_event(&packet);
+        }
+        if !self.stale {
+            return Ok(self.current.clone());
+        }
+        let (root, active_window_atom, wm_pid_atom) = (self.root, self.active_window_atom, self.wm_pid_atom);
+        let window = match self.cardinal(root, active_window_atom)? {
+            Some(window) if window != 0 => window,
+            _ => {
+                self.current = None;
+                return Ok(None);
+            }
+        };
+        if window != self.window {
+            self.watch(window)?;
+            self.window = window;
+        }
+        let pid = self.cardinal(window, wm_pid_atom)?;
+        let program = match pid {
+            Some(pid) => process_name(pid)?,
+            None => None,
+        };
+        let title = self.title(window)?;
+        let current = program.map(|program| Window { program: program, title: title, pid: pid });
+        // a window may set its pid after it gets focus, so keep asking until we have a program
+        self.stale = current.is_none();
+        self.current = current.clone();
+        Ok(current)
+    }
+}
@@ -0,0 +1,73 @@
+use chrono::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    Up,
+    Nearest,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Per {
+    Period,
+    Day,
+}
+
+/// How tracked time is rounded for billing, eg. each period up to the next 15 minutes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rounding {
+    pub increment: Duration,
+    pub per: Per,
+    pub mode: Mode,
+}
+
+impl Rounding {
+    pub fn none() -> Rounding {
+        Rounding {
+            increment: Duration::zero(),
+            per: Per::Period,
+            mode: Mode::Nearest,
+        }
+    }
+
+    pub fn round(&self, duration: Duration) -> Duration {
+        let increment = self.increment.num_seconds();
+        if increment <= 0 {
+            return duration;
+        }
+        let seconds = duration.num_seconds();
+        let increments = match self.mode {
+            Mode::Up => (seconds + increment - 1) / increment,
+            Mode::Nearest => (seconds + increment / 2) / increment,
+        };
+        Duration::seconds(increments * increment)
+    }
+
+    /// The rounded time for each day, in the order the days first appear. Periods count towards
+    /// the day they start on.
+    pub fn day_totals<Tz: TimeZone>(&self,
+                                    periods: &[(DateTime<Tz>, DateTime<Tz>)])
+                                    -> Vec<(Date<Tz>, Duration)> {
+        let mut days: Vec<(Date<Tz>, Duration)> = vec![];
+        for &(ref start, ref end) in periods {
+            let mut time = end.clone() - start.clone();
+            if self.per == Per::Period {
+                time = self.round(time);
+            }
+            let day = start.date();
+            match days.iter().position(|&(ref d, _)| *d == day) {
+                Some(i) => days[i].1 = days[i].1 + time,
+                None => days.push((day, time)),
+            }
+        }
+        if self.per == Per::Day {
+            for day in &mut days {
+                day.1 = self.round(day.1);
+            }
+        }
+        days
+    }
+
+    pub fn total<Tz: TimeZone>(&self, periods: &[(DateTime<Tz>, DateTime<Tz>)]) -> Duration {
+        self.day_totals(periods).into_iter().fold(Duration::zero(), |total, (_, time)| total + time)
+    }
+}
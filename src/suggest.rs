@@ -0,0 +1,118 @@
+//! Working out which project is being worked on from where timmy is run and what has focus.
+
+use std::path::{Path, PathBuf};
+use chrono::*;
+use regex::Regex;
+use probe::Window;
+
+pub enum Match {
+    /// Directories inside this one
+    Path(PathBuf),
+    /// Repositories with a remote whose url contains this, eg. "github.com/mattyhall/timmy"
+    Remote(String),
+    /// Windows whose title matches this
+    Title(Regex),
+}
+
+pub struct Rule {
+    pub project: String,
+    pub on: Match,
+}
+
+impl Rule {
+    /// A rule of the kind `path`, `remote` or `title`. Returns None for any other kind, or if a title
+    /// isn't a valid regex.
+    pub fn new(project: &str, kind: &str, value: &str) -> Option<Rule> {
+        let on = match kind {
+            "path" => Match::Path(value.into()),
+            "remote" => Match::Remote(value.into()),
+            "title" => Match::Title(Regex::new(value).ok()?),
+            _ => return None,
+        };
+        Some(Rule {
+            project: project.into(),
+            on: on,
+        })
+    }
+}
+
+/// The project for a directory with the given git remotes. The rule for the innermost path
+/// containing the directory wins, and remotes are only looked at when no path matches.
+pub fn for_directory<'a>(rules: &'a [Rule], dir: &Path, remotes: &[String]) -> Option<&'a str> {
+    let mut best: Option<(usize, &str)> = None;
+    for rule in rules {
+        if let Match::Path(ref path) = rule.on {
+            let depth = path.components().count();
+            if dir.starts_with(path) && best.map_or(true, |(best, _)| depth > best) {
+                best = Some((depth, &rule.project));
+            }
+        }
+    }
+    if let Some((_, project)) = best {
+        return Some(project);
+    }
+    rules.iter()
+        .find(|rule| match rule.on {
+            // scp-like urls such as git@github.com:mattyhall/timmy.git separate the host with a colon
+            Match::Remote(ref remote) => remotes.iter().any(|url| url.replace(':', "/").contains(&remote[..])),
+            _ => false,
+        })
+        .map(|rule| &rule.project[..])
+}
+
+/// The project of the first rule matching the window's title.
+pub fn for_window<'a>(rules: &'a [Rule], window: &Window) -> Option<&'a str> {
+    let title = match window.title {
+        Some(ref title) => title,
+        None => return None,
+    };
+    rules.iter()
+        .find(|rule| match rule.on {
+            Match::Title(ref regex) => regex.is_match(title),
+            _ => false,
+        })
+        .map(|rule| &rule.project[..])
+}
+
+/// Notices when focus stays on windows belonging to a project other than the one being tracked.
+pub struct Drift {
+    rules: Vec<Rule>,
+    project: String,
+    min: Duration,
+    /// The other project focus is on and since when, and whether it has been warned about
+    other: Option<(String, DateTime<Local>, bool)>,
+}
+
+impl Drift {
+    pub fn new(rules: Vec<Rule>, project: &str, min: Duration) -> Drift {
+        Drift {
+            rules: rules,
+            project: project.into(),
+            min: min,
+            other: None,
+        }
+    }
+
+    /// Looks at what has focus now, returning the other project the first time focus has been on
+    /// its windows for `min` without a break.
+    pub fn observe(&mut self, window: Option<&Window>, now: DateTime<Local>) -> Option<String> {
+        let project = match window.and_then(|window| for_window(&self.rules, window)) {
+            Some(project) if project != self.project => project.to_string(),
+            _ => {
+                self.other = None;
+                return None;
+            }
+        };
+        match self.other {
+            Some((ref other, _, _)) if *other == project => {}
+            _ => self.other = Some((project, now, false)),
+        }
+        match self.other {
+            Some((ref other, since, ref mut warned)) if !*warned && now - since >= self.min => {
+                *warned = true;
+                Some(other.clone())
+            }
+            _ => None,
+        }
+    }
+}
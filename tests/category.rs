@@ -0,0 +1,45 @@
+extern crate chrono;
+extern crate timmy;
+
+use chrono::*;
+use timmy::category::*;
+use timmy::probe::Segment;
+
+fn segment(program: &str, title: Option<&str>, minutes: i64) -> Segment {
+    let start = Local.ymd(2016, 8, 23).and_hms(9, 0, 0);
+    Segment {
+        program: program.into(),
+        title: title.map(|title| title.into()),
+        start: start,
+        end: start + Duration::minutes(minutes),
+        estimated: false,
+    }
+}
+
+fn rules() -> Vec<Rule> {
+    vec![Rule::new("code review", Some("firefox"), Some(r"github\.com/.*/pull")).unwrap(),
+         Rule::new("rust editing", None, Some(r"\.rs - emacs$")).unwrap(),
+         Rule::new("browsing", Some("firefox"), None).unwrap()]
+}
+
+#[test]
+fn test_categorise() {
+    let rules = rules();
+    let review = segment("firefox", Some("github.com/mattyhall/timmy/pull/3 - Mozilla Firefox"), 10);
+    assert_eq!(categorise(&rules, &review), Some("code review"));
+    assert_eq!(categorise(&rules, &segment("emacs", Some("main.rs - emacs"), 10)), Some("rust editing"));
+    assert_eq!(categorise(&rules, &segment("emacs", Some("notes.org - emacs"), 10)), None);
+    assert_eq!(categorise(&rules, &segment("firefox", None, 10)), Some("browsing"));
+    assert_eq!(categorise(&rules, &segment("chromium", Some("github.com/a/b/pull/1"), 10)), None);
+    assert!(Rule::new("broken", None, Some("(")).is_none());
+}
+
+#[test]
+fn test_totals() {
+    let segments = vec![segment("emacs", Some("main.rs - emacs"), 20),
+                        segment("firefox", Some("github.com/a/b/pull/1"), 5),
+                        segment("emacs", Some("lib.rs - emacs"), 10),
+                        segment("xterm", Some("cargo test"), 30)];
+    assert_eq!(totals(&rules(), &segments),
+               vec![("rust editing".into(), 30 * 60), ("code review".into(), 5 * 60)]);
+}
@@ -60,3 +60,131 @@ fn test_kitchen_sink() {
     let yesterday_two = now() - Duration::days(1) - Duration::hours(2) - Duration::minutes(30);
     assert_eq!(parse_datetime("yesterday 14:00", now()), Some(yesterday_two));
 }
+
+#[test]
+fn test_last_weekday() {
+    // now() is a Tuesday
+    assert_eq!(parse_datetime("last monday", now()), Some(now() - Duration::days(1)));
+    assert_eq!(parse_datetime("last tue", now()), Some(now() - Duration::days(7)));
+    assert_eq!(parse_datetime("last friday", now()), Some(now() - Duration::days(4)));
+    let monday_nine = now() - Duration::days(1) - Duration::hours(7) - Duration::minutes(30);
+    assert_eq!(parse_datetime("last monday 09:00", now()), Some(monday_nine));
+    assert_eq!(parse_datetime("Last Monday 09:00", now()), Some(monday_nine));
+    assert_eq!(parse_datetime("last Monday", now()), Some(now() - Duration::days(1)));
+    assert_eq!(parse_datetime("last someday", now()), None);
+}
+
+#[test]
+fn test_unknown() {
+    assert_eq!(parse_datetime("", now()), None);
+    assert_eq!(parse_datetime("whenever", now()), None);
+}
+
+#[test]
+fn test_duration() {
+    let ninety_mins = Duration::minutes(90);
+    assert_eq!(parse_duration("1h30m"), Some(ninety_mins));
+    assert_eq!(parse_duration("90 minutes"), Some(ninety_mins));
+    assert_eq!(parse_duration("90m"), Some(ninety_mins));
+    assert_eq!(parse_duration("1.5h"), Some(ninety_mins));
+    assert_eq!(parse_duration("1 hr 30 mins"), Some(ninety_mins));
+    assert_eq!(parse_duration("2 hours 15 mins"), Some(Duration::minutes(135)));
+    assert_eq!(parse_duration("45s"), Some(Duration::seconds(45)));
+}
+
+#[test]
+fn test_invalid_duration() {
+    assert_eq!(parse_duration(""), None);
+    assert_eq!(parse_duration("90"), None);
+    assert_eq!(parse_duration("1h and a bit"), None);
+    assert_eq!(parse_duration("yesterday"), None);
+}
+
+#[test]
+fn test_schedule() {
+    let standup = parse_schedule("weekdays 09:30 for 15m").unwrap();
+    assert_eq!(standup.days, vec![Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri]);
+    assert_eq!((standup.hour, standup.minute), (9, 30));
+    assert_eq!(standup.duration, Duration::minutes(15));
+
+    let review = parse_schedule("Mon, Thursday at 14:00 for 1h").unwrap();
+    assert_eq!(review.days, vec![Weekday::Mon, Weekday::Thu]);
+    assert_eq!((review.hour, review.minute), (14, 0));
+    assert_eq!(review.duration, Duration::hours(1));
+
+    assert_eq!(parse_schedule("daily 17:00 for 30 mins").unwrap().days.len(), 7);
+    assert_eq!(parse_schedule("weekdays 09:30"), None);
+    assert_eq!(parse_schedule("someday 09:30 for 15m"), None);
+    assert_eq!(parse_schedule("weekdays 25:00 for 15m"), None);
+}
+
+#[test]
+fn test_schedule_occurrences() {
+    // now() is Tuesday 23rd August 2016 16:30
+    let standup = parse_schedule("weekdays 09:30 for 15m").unwrap();
+    let since = now() - Duration::days(7);
+    let occurrences = standup.occurrences(since, now());
+    let first = DateTime::parse_from_rfc3339("2016-08-17T09:30:00+01:00").unwrap();
+    let last = DateTime::parse_from_rfc3339("2016-08-23T09:30:00+01:00").unwrap();
+    // Tuesday 16th's is before since, and there are none on the weekend
+    assert_eq!(occurrences.len(), 5);
+    assert_eq!(occurrences[0], first);
+    assert_eq!(occurrences[4], last);
+    assert!(standup.occurrences(now(), now() + Duration::hours(1)).is_empty());
+}
+
+#[test]
+fn test_working_hours() {
+    let hours = parse_working_hours("Mon-Fri 09:00-17:30").unwrap();
+    assert_eq!(hours.len(), 1);
+    assert_eq!(hours[0].days, vec![Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri]);
+    assert_eq!(hours[0].start, NaiveTime::from_hms(9, 0, 0));
+    assert_eq!(hours[0].end, NaiveTime::from_hms(17, 30, 0));
+
+    let hours = parse_working_hours("mon,wed 08:00 - 12:00; fri 10:00-14:00").unwrap();
+    assert_eq!(hours[0].days, vec![Weekday::Mon, Weekday::Wed]);
+    assert_eq!(hours[1].days, vec![Weekday::Fri]);
+
+    assert_eq!(parse_working_hours("sat-mon 10:00-11:00").unwrap()[0].days,
+               vec![Weekday::Sat, Weekday::Sun, Weekday::Mon]);
+    assert_eq!(parse_working_hours("mon-fri 17:30-09:00"), None);
+    assert_eq!(parse_working_hours("mon-fri"), None);
+    assert_eq!(parse_working_hours("someday 09:00-17:00"), None);
+}
+
+#[test]
+fn test_working_intervals() {
+    // now() is Tuesday 23rd August 2016 16:30
+    let hours = parse_working_hours("mon-fri 09:00-17:30").unwrap();
+    let since = now() - Duration::days(3);
+    let intervals = working_intervals(&hours, since, now());
+    let at = |s: &str| DateTime::parse_from_rfc3339(&format!("2016-08-{}:00+01:00", s)).unwrap();
+    // Saturday 20th 16:30 until now, so just Monday and most of Tuesday
+    assert_eq!(intervals, vec![(at("22T09:00"), at("22T17:30")), (at("23T09:00"), at("23T16:30"))]);
+}
+
+#[test]
+fn test_working_time() {
+    let hours = parse_working_hours("mon-thu 09:00-17:00; fri 09:00-13:00").unwrap();
+    let monday = now() - Duration::days(1) - Duration::hours(16) - Duration::minutes(30);
+    let week = monday + Duration::days(7);
+    assert_eq!(working_time(&hours, monday, week, &[]), Duration::hours(36));
+    // Tuesday 23rd is off and Saturday 27th isn't a working day anyway
+    let off = [NaiveDate::from_ymd(2016, 8, 23), NaiveDate::from_ymd(2016, 8, 27)];
+    assert_eq!(working_time(&hours, monday, week, &off), Duration::hours(28));
+    assert_eq!(working_time(&hours, monday, now(), &[]), Duration::hours(8) + Duration::minutes(450));
+}
+
+#[test]
+fn test_parse_end() {
+    let start = now() - Duration::days(1) - Duration::hours(4) - Duration::minutes(30);
+    let yesterday = |hours: i64| start + Duration::hours(hours);
+    // start is yesterday at 12:00
+    assert_eq!(parse_end("+90m", start, now()), Some(start + Duration::minutes(90)));
+    assert_eq!(parse_end("13:00", start, now()), Some(yesterday(1)));
+    assert_eq!(parse_end("yesterday 13:00", start, now()), Some(yesterday(1)));
+    assert_eq!(parse_end("16:30", now(), now()), Some(now()));
+    // an end before the start is returned as it is, for the caller to reject
+    assert_eq!(parse_end("11:00", start, now()), Some(yesterday(-1)));
+    assert_eq!(parse_end("+soon", start, now()), None);
+}
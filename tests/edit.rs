@@ -0,0 +1,44 @@
+extern crate chrono;
+extern crate timmy;
+
+use chrono::*;
+use timmy::edit::*;
+
+fn line(id: Option<i64>, project: &str, description: Option<&str>) -> Line {
+    Line {
+        id: id,
+        project: project.into(),
+        start: Local.ymd(2016, 8, 23).and_hms(9, 0, 0),
+        end: Local.ymd(2016, 8, 23).and_hms(10, 30, 0),
+        description: description.map(|d| d.into()),
+    }
+}
+
+#[test]
+fn test_render() {
+    assert_eq!(render(&line(Some(12), "timmy", Some("Planning"))),
+               "12 timmy 23/08/16 09:00 23/08/16 10:30 Planning");
+    assert_eq!(render(&line(None, "client site", None)),
+               "new \"client site\" 23/08/16 09:00 23/08/16 10:30");
+    assert_eq!(render(&line(Some(3), "say \"hi\"", None)),
+               "3 \"say \\\"hi\\\"\" 23/08/16 09:00 23/08/16 10:30");
+}
+
+#[test]
+fn test_parse() {
+    for line in vec![line(Some(12), "timmy", Some("Planning the  week")),
+                     line(None, "client site", None),
+                     line(Some(3), "say \"hi\"", Some("x")),
+                     line(Some(4), "\"quoted", None),
+                     line(Some(5), "back\\slash", None)] {
+        let mut expected = line.clone();
+        expected.description = line.description.as_ref().map(|d| d.split_whitespace().collect::<Vec<_>>().join(" "));
+        assert_eq!(parse(&render(&line)), Some(expected));
+    }
+    assert_eq!(parse("  7   client  23/08/16 09:00 23/08/16 10:30  "), Some(line(Some(7), "client", None)));
+    assert_eq!(parse("7 \"client site 23/08/16 09:00 23/08/16 10:30"), None);
+    assert_eq!(parse("7 client 23/08/16 09:00 23/08/16"), None);
+    assert_eq!(parse("x client 23/08/16 09:00 23/08/16 10:30"), None);
+    assert_eq!(parse("7 \"\" 23/08/16 09:00 23/08/16 10:30"), None);
+    assert_eq!(parse("7"), None);
+}
@@ -0,0 +1,58 @@
+extern crate chrono;
+extern crate timmy;
+
+use chrono::*;
+use timmy::focus::*;
+use timmy::probe::Segment;
+
+fn segment(program: &str, title: &str, start: u32, end: u32) -> Segment {
+    Segment {
+        program: program.into(),
+        title: Some(title.into()),
+        start: Local.ymd(2016, 8, 23).and_hms(9, start, 0),
+        end: Local.ymd(2016, 8, 23).and_hms(9, end, 0),
+        estimated: false,
+    }
+}
+
+fn segments() -> Vec<Segment> {
+    vec![segment("emacs", "main.rs - emacs", 2, 20),
+         segment("emacs", "lib.rs - emacs", 20, 40),
+         segment("firefox", "Inbox - Firefox", 40, 43),
+         segment("emacs", "lib.rs - emacs", 43, 50),
+         // nothing had focus between 50 and 52
+         segment("emacs", "lib.rs - emacs", 52, 55),
+         segment("xterm", "cargo test", 55, 59)]
+}
+
+#[test]
+fn test_timeline() {
+    let stretches = timeline(&segments());
+    let summary: Vec<_> = stretches.iter()
+        .map(|stretch| (&stretch.program[..], stretch.start.minute(), stretch.end.minute()))
+        .collect();
+    assert_eq!(summary,
+               vec![("emacs", 2, 40), ("firefox", 40, 43), ("emacs", 43, 50), ("emacs", 52, 55), ("xterm", 55, 59)]);
+    assert!(timeline(&[]).is_empty());
+}
+
+#[test]
+fn test_metrics() {
+    let measured = metrics(&segments(), &["emacs".into(), "xterm".into()]);
+    assert_eq!(measured.switches, 3);
+    assert_eq!(measured.focus, Duration::minutes(52));
+    assert_eq!(measured.total, Duration::minutes(55));
+    assert!((measured.focus_share() - 52.0 / 55.0 * 100.0).abs() < 1e-9);
+    assert_eq!(measured.longest.map(|stretch| (stretch.duration(), stretch.program)),
+               Some((Duration::minutes(38), "emacs".into())));
+
+    let mut estimated = segments();
+    estimated.push(Segment { estimated: true, ..segment("firefox", "Rust", 59, 59) });
+    estimated.push(Segment { estimated: true, ..segment("emacs", "lib.rs - emacs", 0, 30) });
+    assert_eq!(metrics(&estimated, &["emacs".into(), "xterm".into()]),
+               metrics(&segments(), &["emacs".into(), "xterm".into()]));
+    assert_eq!(timeline(&estimated), timeline(&segments()));
+
+    let empty = metrics(&[], &[]);
+    assert_eq!((empty.switches, empty.focus_share(), empty.longest), (0, 0.0, None));
+}
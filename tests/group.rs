@@ -0,0 +1,24 @@
+extern crate chrono;
+extern crate timmy;
+
+use chrono::*;
+use timmy::group::*;
+use timmy::probe::Segment;
+
+fn alias(program: &str, tool: &str) -> Alias {
+    Alias { program: program.into(), tool: tool.into() }
+}
+
+#[test]
+fn test_apply() {
+    let aliases = vec![alias("code", "vscode"), alias("code-oss", "vscode"), alias("codium", "vscode")];
+    assert_eq!(tool(&aliases, "codium"), "vscode");
+    assert_eq!(tool(&aliases, "emacs"), "emacs");
+    let start = Local.ymd(2016, 8, 23).and_hms(9, 0, 0);
+    let segment = |program: &str, title: Option<&str>| {
+        Segment { program: program.into(), title: title.map(|title| title.into()), start: start, end: start, estimated: false }
+    };
+    let segments = vec![segment("code-oss", Some("main.rs")), segment("emacs", None)];
+    let programs: Vec<_> = apply(&aliases, segments).into_iter().map(|segment| segment.program).collect();
+    assert_eq!(programs, vec!["vscode".to_string(), "emacs".into()]);
+}
@@ -0,0 +1,79 @@
+extern crate chrono;
+extern crate timmy;
+
+use chrono::*;
+use timmy::invoice::*;
+
+fn invoice() -> Invoice {
+    Invoice {
+        number: 7,
+        provisional: false,
+        customer: "Smith & Sons".into(),
+        date: NaiveDate::from_ymd(2016, 9, 1),
+        since: NaiveDate::from_ymd(2016, 8, 1),
+        until: NaiveDate::from_ymd(2016, 8, 31),
+        currency: Some("GBP".into()),
+        lines: vec![Line { project: "website".into(), hours: 10.5, rate: 4000 },
+                    Line { project: "app".into(), hours: 1.0 / 3.0, rate: 5000 }],
+        expenses: vec![],
+    }
+}
+
+#[test]
+fn test_total() {
+    let invoice = invoice();
+    // billed as the 0.33 hours shown, not a third of an hour
+    assert_eq!(invoice.lines[1].billed_hours(), 33);
+    assert_eq!(invoice.lines[1].amount(), 1650);
+    assert_eq!(invoice.total(), 43650);
+    assert_eq!(Line { project: "app".into(), hours: 0.125, rate: 3333 }.amount(), 433);
+    assert_eq!(format_cents(-1205), "-12.05");
+    assert_eq!(cents(0.1 + 0.2), 30);
+}
+
+#[test]
+fn test_markdown() {
+    let markdown = invoice().render(Format::Markdown);
+    assert!(markdown.starts_with("# Invoice 0007\n"));
+    assert!(markdown.contains("| website | 10.50 | 40.00 GBP | 420.00 GBP |\n"));
+    assert!(markdown.contains("**Total: 436.50 GBP**"));
+    assert!(!markdown.contains("Expenses"));
+
+    let mut invoice = invoice();
+    invoice.provisional = true;
+    invoice.lines[0].project = "web | app".into();
+    let markdown = invoice.render(Format::Markdown);
+    assert!(markdown.starts_with("# Invoice 0007 (provisional number)\n"));
+    assert!(markdown.contains("| web \\| app | 10.50 |"));
+}
+
+#[test]
+fn test_html() {
+    let html = invoice().render(Format::Html);
+    assert!(html.contains("Customer: Smith &amp; Sons"));
+    assert!(html.contains("<td>app</td><td>0.33</td><td>50.00 GBP</td><td>16.50 GBP</td>"));
+}
+
+#[test]
+fn test_text() {
+    let text = invoice().render(Format::Text);
+    assert!(text.contains("Period: 01/08/2016 - 31/08/2016\n"));
+    assert!(text.contains("website  10.50  40.00 GBP  420.00 GBP\n"));
+    assert!(text.contains("app       0.33  50.00 GBP   16.50 GBP\n"));
+}
+
+#[test]
+fn test_expenses() {
+    let mut invoice = invoice();
+    invoice.expenses.push(Expense {
+        date: NaiveDate::from_ymd(2016, 8, 12),
+        project: "website".into(),
+        category: Some("travel".into()),
+        note: Some("train to London".into()),
+        amount: 6350,
+    });
+    assert_eq!(invoice.total(), 50000);
+    let text = invoice.render(Format::Text);
+    assert!(text.contains("12/08/2016  website  travel: train to London  63.50 GBP\n"));
+    assert!(text.contains("Total: 500.00 GBP\n"));
+}
@@ -0,0 +1,56 @@
+extern crate chrono;
+extern crate timmy;
+
+use chrono::*;
+use timmy::pattern::Pattern;
+use timmy::privacy::*;
+use timmy::probe::Segment;
+
+fn segment(program: &str, title: &str, minute: u32) -> Segment {
+    Segment {
+        program: program.into(),
+        title: Some(title.into()),
+        start: Local.ymd(2016, 8, 23).and_hms(9, minute, 0),
+        end: Local.ymd(2016, 8, 23).and_hms(9, minute + 1, 0),
+        estimated: false,
+    }
+}
+
+fn rule(action: Action, program: Option<&str>, title: Option<&str>) -> Rule {
+    Rule { action: action, pattern: Pattern::new(program, title).unwrap() }
+}
+
+#[test]
+fn test_apply() {
+    let rules = vec![rule(Action::Redact, Some("firefox"), Some("Private Browsing")),
+                     rule(Action::Ignore, Some("keepassxc"), None),
+                     rule(Action::Redact, None, Some("(?i)acme")),
+                     rule(Action::Ignore, None, Some("ACME passwords"))];
+    let segments = vec![segment("emacs", "main.rs - emacs", 0),
+                        segment("firefox", "Shopping - Private Browsing", 1),
+                        segment("keepassxc", "Passwords", 2),
+                        segment("thunderbird", "Re: Acme contract", 3),
+                        segment("emacs", "ACME passwords", 4)];
+    let applied = apply(&rules, segments.clone());
+    assert_eq!(applied.len(), 3);
+    assert_eq!(applied[0], segments[0]);
+    assert_eq!((&applied[1].program[..], &applied[1].title, applied[1].start),
+               (PRIVATE, &None, segments[1].start));
+    assert_eq!((&applied[2].program[..], &applied[2].title), (PRIVATE, &None));
+}
+
+#[test]
+fn test_apply_terminal() {
+    let segments = vec![segment("xterm/nvim", "notes.md", 0),
+                        segment("xterm/bash", "~", 1),
+                        segment("gnome-terminal-/nvim", "notes.md", 2)];
+    let programs = |rules: &[Rule]| -> Vec<String> {
+        apply(rules, segments.clone()).into_iter().map(|segment| segment.program).collect()
+    };
+    assert_eq!(programs(&[rule(Action::Ignore, Some("nvim"), None)]), vec!["xterm/bash"]);
+    assert_eq!(programs(&[rule(Action::Redact, Some("xterm"), None)]),
+               vec![PRIVATE, PRIVATE, "gnome-terminal-/nvim"]);
+    assert_eq!(programs(&[rule(Action::Ignore, Some("xterm/bash"), None)]),
+               vec!["xterm/nvim", "gnome-terminal-/nvim"]);
+    assert_eq!(programs(&[rule(Action::Ignore, Some("vim"), None)]).len(), 3);
+}
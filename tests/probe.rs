@@ -0,0 +1,309 @@
+extern crate chrono;
+extern crate timmy;
+
+use chrono::*;
+use timmy::probe::*;
+
+fn at(minute: u32, second: u32) -> DateTime<Local> {
+    Local.ymd(2016, 8, 23).and_hms(9, minute, second)
+}
+
+fn window(program: &str, title: &str) -> Option<Window> {
+    Some(Window::new(program, Some(title)))
+}
+
+fn segment(program: &str, title: &str, start: DateTime<Local>, end: DateTime<Local>) -> Segment {
+    Segment { program: program.into(), title: Some(title.into()), start: start, end: end, estimated: false }
+}
+
+#[test]
+fn test_tracker() {
+    let probe = Fake::new(&[window("vim", "main.rs"),
+                            window("vim", "main.rs"),
+                            window("firefox", "Rust"),
+                            None,
+                            window("vim", "main.rs"),
+                            window("vim", "lib.rs")]);
+    let mut tracker = ProgramTracker::new(probe, at(0, 0));
+    tracker.poll(at(1, 0));
+    tracker.poll(at(1, 30));
+    tracker.poll(at(2, 0));
+    tracker.poll(at(10, 0));
+    tracker.poll(at(12, 0));
+    let segments = tracker.finish(at(15, 0));
+    assert_eq!(segments,
+               vec![segment("vim", "main.rs", at(0, 0), at(1, 30)),
+                    segment("firefox", "Rust", at(1, 30), at(2, 0)),
+                    segment("vim", "main.rs", at(10, 0), at(12, 0)),
+                    segment("vim", "lib.rs", at(12, 0), at(15, 0))]);
+    assert_eq!(totals(&segments), vec![("vim".into(), 90 + 5 * 60), ("firefox".into(), 30)]);
+}
+
+#[test]
+fn test_clip() {
+    let segment = segment("vim", "main.rs", at(10, 0), at(20, 0));
+    assert_eq!(segment.clip(at(0, 0), at(30, 0)), Some(segment.clone()));
+    assert_eq!(segment.clip(at(15, 0), at(30, 0)).unwrap().start, at(15, 0));
+    assert_eq!(segment.clip(at(0, 0), at(12, 0)).unwrap().end, at(12, 0));
+    assert_eq!(segment.clip(at(20, 0), at(30, 0)), None);
+}
+
+#[test]
+fn test_settle() {
+    let segments = vec![segment("slack", "general", at(0, 0), at(0, 1)),
+                        segment("vim", "main.rs", at(0, 1), at(5, 0)),
+                        segment("firefox", "Rust", at(5, 0), at(5, 1)),
+                        segment("xterm", "bash", at(5, 1), at(5, 2)),
+                        segment("vim", "main.rs", at(5, 2), at(8, 0)),
+                        segment("firefox", "Rust", at(8, 0), at(9, 0)),
+                        // nothing had focus until 10:00
+                        segment("xterm", "bash", at(10, 0), at(10, 1))];
+    assert_eq!(settle(segments.clone(), Duration::seconds(2)),
+               vec![segment("vim", "main.rs", at(0, 0), at(8, 0)),
+                    segment("firefox", "Rust", at(8, 0), at(9, 0)),
+                    segment("xterm", "bash", at(10, 0), at(10, 1))]);
+    assert_eq!(settle(segments.clone(), Duration::zero()), segments);
+
+    let estimated = |program, start, end| Segment { estimated: true, ..segment(program, "", start, end) };
+    let segments = vec![estimated("cargo", at(0, 0), at(0, 1)),
+                        estimated("vim", at(0, 1), at(0, 2)),
+                        segment("vim", "main.rs", at(0, 2), at(5, 0)),
+                        estimated("cargo", at(5, 0), at(5, 1))];
+    assert_eq!(settle(segments.clone(), Duration::seconds(2)), segments);
+}
+
+#[test]
+fn test_detect() {
+    fn env(vars: &[(&str, &str)]) -> Option<Backend> {
+        detect(|name| vars.iter().find(|&&(k, _)| k == name).map(|&(_, v)| v.to_string()))
+    }
+    assert_eq!(env(&[("DISPLAY", ":0")]), Some(Backend::X11));
+    assert_eq!(env(&[("DISPLAY", ":0"), ("SWAYSOCK", "/run/sway.sock"), ("WAYLAND_DISPLAY", "wayland-1")]),
+               Some(Backend::Sway));
+    assert_eq!(env(&[("DISPLAY", ":0"), ("WAYLAND_DISPLAY", "wayland-0")]), None);
+    assert_eq!(env(&[("DISPLAY", "")]), None);
+    assert_eq!(env(&[]), None);
+}
+
+#[test]
+fn test_parse_display() {
+    use timmy::probe::x11::Display;
+    assert_eq!(x11::parse_display(":0"), Some(Display { host: None, number: 0 }));
+    assert_eq!(x11::parse_display("unix:1.0"), Some(Display { host: None, number: 1 }));
+    assert_eq!(x11::parse_display("remote:10.0"), Some(Display { host: Some("remote".into()), number: 10 }));
+    assert_eq!(x11::parse_display("wayland-0"), None);
+}
+
+#[test]
+fn test_xauthority() {
+    fn entry(family: u16, address: &str, number: &str, name: &str, data: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![(family >> 8) as u8, family as u8];
+        for field in &[address.as_bytes(), number.as_bytes(), name.as_bytes(), data] {
+            bytes.extend_from_slice(&[0, field.len() as u8]);
+            bytes.extend_from_slice(field);
+        }
+        bytes
+    }
+    let mut file = entry(256, "laptop", "1", "MIT-MAGIC-COOKIE-1", &[1, 2, 3]);
+    file.extend(entry(65535, "", "0", "MIT-MAGIC-COOKIE-1", &[4, 5, 6]));
+    file.extend(entry(256, "laptop", "0", "MIT-MAGIC-COOKIE-1", &[7, 8, 9]));
+    file.extend(entry(256, "laptop", "0", "XDM-AUTHORIZATION-1", &[0]));
+    file.extend_from_slice(&[1, 0, 0]);
+    let entries = x11::parse_xauthority(&file);
+    assert_eq!(entries.len(), 4);
+    assert_eq!(entries[0].address, b"laptop".to_vec());
+    assert_eq!(entries[0].number, "1");
+    assert_eq!(entries[3].name, "XDM-AUTHORIZATION-1");
+    assert_eq!(x11::find_cookie(&entries, 0, "laptop", &[]).unwrap().data, vec![7, 8, 9]);
+    assert_eq!(x11::find_cookie(&entries, 0, "desktop", &[]).unwrap().data, vec![4, 5, 6]);
+    assert_eq!(x11::find_cookie(&entries, 1, "laptop", &[]).unwrap().data, vec![1, 2, 3]);
+    assert!(x11::find_cookie(&entries, 2, "laptop", &[]).is_none());
+}
+
+#[test]
+fn test_remote_cookie() {
+    use std::net::IpAddr;
+    let entry = |family: u16, address: &[u8], number: &str, data: u8| x11::AuthEntry {
+        family: family,
+        address: address.to_vec(),
+        number: number.into(),
+        name: "MIT-MAGIC-COOKIE-1".into(),
+        data: vec![data],
+    };
+    let entries = vec![entry(256, b"laptop", "10", 1),
+                       entry(0, &[192, 168, 0, 2], "0", 2),
+                       entry(6, &[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1], "0", 3)];
+    let loopback: IpAddr = "127.0.0.1".parse().unwrap();
+    let remote: IpAddr = "192.168.0.2".parse().unwrap();
+    let elsewhere: IpAddr = "10.0.0.1".parse().unwrap();
+    // ssh -X forwards localhost:10 and adds a cookie for this host
+    assert_eq!(x11::find_cookie(&entries, 10, "laptop", &[loopback]).unwrap().data, vec![1]);
+    assert_eq!(x11::find_cookie(&entries, 0, "laptop", &[remote]).unwrap().data, vec![2]);
+    assert_eq!(x11::find_cookie(&entries, 0, "laptop", &["::1".parse().unwrap()]).unwrap().data, vec![3]);
+    assert!(x11::find_cookie(&entries, 10, "laptop", &[elsewhere]).is_none());
+}
+
+const TREE: &'static str = r#"{
+  "id": 1, "type": "root", "focused": false, "name": "root",
+  "nodes": [
+    {"id": 3, "type": "output", "name": "eDP-1", "focused": false, "nodes": [
+      {"id": 4, "type": "workspace", "name": "1", "focused": false, "nodes": [
+        {"id": 5, "type": "con", "name": "~/src/timmy \"vim\"", "focused": false, "pid": 1200,
+         "app_id": "foot", "nodes": [], "floating_nodes": []}
+      ], "floating_nodes": [
+        {"id": 6, "type": "floating_con", "name": "Firefox", "focused": true, "pid": 1300,
+         "app_id": null, "window_properties": {"class": "firefox"}, "nodes": [], "floating_nodes": []}
+      ]}
+    ], "floating_nodes": []}
+  ],
+  "floating_nodes": []
+}"#;
+
+#[test]
+fn test_sway_focused_window() {
+    assert_eq!(sway::focused_window(TREE).unwrap(), Some((1300, Some("Firefox".into()))));
+    let unfocused = TREE.replace("\"focused\": true", "\"focused\": false");
+    assert_eq!(sway::focused_window(&unfocused).unwrap(), None);
+    let workspace = r#"{"id": 1, "focused": false, "nodes": [{"id": 4, "type": "workspace", "focused": true,
+                        "nodes": [], "floating_nodes": []}]}"#;
+    assert_eq!(sway::focused_window(workspace).unwrap(), None);
+    assert!(sway::focused_window("{\"nodes\": [").is_err());
+}
+
+#[test]
+fn test_parse_stat() {
+    let stat = "4242 (tmux: client) S 4200 4242 4200 34817 4242 4194304 301 0 0 0 1 0 0 0 20 0 1 0";
+    assert_eq!(procfs::parse_stat(stat),
+               Some(procfs::Process {
+                   pid: 4242,
+                   comm: "tmux: client".into(),
+                   ppid: 4200,
+                   tty_nr: 34817,
+                   tpgid: 4242,
+                   cpu: 1,
+               }));
+    let stat = "17 (a) b) (c) S 1 17 17 0 -1 4194560 0 0 0 0 0 0 0 0 20 0 1 0";
+    assert_eq!(procfs::parse_stat(stat).map(|p| (p.comm, p.tpgid)), Some(("a) b) (c".into(), -1)));
+    assert_eq!(procfs::parse_stat("17 (truncated) S 1"), None);
+}
+
+fn stat(pid: u32, comm: &str, ppid: u32, tty: i32, tpgid: i32, cpu: u64) -> String {
+    format!("{} ({}) S {} {} {} {} {} 4194304 0 0 0 0 {} 0 0 0 20 0 1 0", pid, comm, ppid, pid, pid, tty, tpgid, cpu)
+}
+
+#[test]
+fn test_terminals() {
+    use std::fs::{self, File};
+    use std::io::Write;
+    use std::os::unix::fs::symlink;
+
+    let root = std::env::temp_dir().join(format!("timmy-proc-{}", std::process::id()));
+    let process = |pid: u32, stat: &str, children: &str, tty: Option<&str>| {
+        let dir = root.join(pid.to_string());
+        fs::create_dir_all(dir.join("task").join(pid.to_string())).unwrap();
+        fs::create_dir_all(dir.join("fd")).unwrap();
+        File::create(dir.join("stat")).unwrap().write_all(stat.as_bytes()).unwrap();
+        File::create(dir.join("task").join(pid.to_string()).join("children"))
+            .unwrap()
+            .write_all(children.as_bytes())
+            .unwrap();
+        if let Some(tty) = tty {
+            symlink(tty, dir.join("fd").join("0")).unwrap();
+        }
+    };
+    process(100, &stat(100, "gnome-terminal-", 1, 0, -1, 0), "200 ", None);
+    process(200, &stat(200, "bash", 100, 34817, 300, 0), "300 ", Some("/dev/pts/1"));
+    process(300, &stat(300, "nvim", 200, 34817, 300, 0), "", Some("/dev/pts/1"));
+    process(400, &stat(400, "xterm", 1, 0, -1, 0), "500 ", None);
+    process(500, &stat(500, "zsh", 400, 34818, 500, 0), "", Some("/dev/pts/2"));
+
+    let terminal = |pid| Some(Window { program: "gnome-terminal-".into(), title: None, pid: Some(pid) });
+    let script = [terminal(100),
+                  Some(Window { program: "xterm".into(), title: None, pid: Some(400) }),
+                  window("nvim", "main.rs"),
+                  terminal(999)];
+    let mut probe = Terminals::with_proc(Fake::new(&script), procfs::Proc::at(&root));
+    let programs: Vec<String> = (0..4).map(|_| probe.current_window().unwrap().unwrap().program).collect();
+    fs::remove_dir_all(&root).unwrap();
+    assert_eq!(programs, vec!["gnome-terminal-/nvim", "xterm/zsh", "nvim", "gnome-terminal-"]);
+}
+
+#[test]
+fn test_terminals_cached() {
+    use std::fs::{self, File};
+    use std::io::Write;
+    use std::os::unix::fs::symlink;
+
+    let root = std::env::temp_dir().join(format!("timmy-proc-cached-{}", std::process::id()));
+    let write = |path: &std::path::Path, contents: &str| File::create(path).unwrap().write_all(contents.as_bytes()).unwrap();
+    for &(pid, children) in &[(100, "200 "), (200, "300 "), (300, "")] {
+        let dir = root.join(pid.to_string());
+        fs::create_dir_all(dir.join("task").join(pid.to_string())).unwrap();
+        fs::create_dir_all(dir.join("fd")).unwrap();
+        write(&dir.join("task").join(pid.to_string()).join("children"), children);
+        if pid != 100 {
+            symlink("/dev/pts/1", dir.join("fd").join("0")).unwrap();
+        }
+    }
+    write(&root.join("100").join("stat"), &stat(100, "xterm", 1, 0, -1, 0));
+    write(&root.join("200").join("stat"), &stat(200, "bash", 100, 34817, 300, 0));
+    write(&root.join("300").join("stat"), &stat(300, "nvim", 200, 34817, 300, 0));
+
+    let xterm = Some(Window { program: "xterm".into(), title: None, pid: Some(100) });
+    let mut probe = Terminals::with_proc(Fake::new(&[xterm.clone(), xterm.clone(), xterm]),
+                                         procfs::Proc::at(&root));
+    let first = probe.current_window().unwrap().unwrap().program;
+    // nvim exits, but the last answer is trusted for a second
+    write(&root.join("200").join("stat"), &stat(200, "bash", 100, 34817, 200, 0));
+    let second = probe.current_window().unwrap().unwrap().program;
+    std::thread::sleep(std::time::Duration::from_millis(1100));
+    let third = probe.current_window().unwrap().unwrap().program;
+    fs::remove_dir_all(&root).unwrap();
+    assert_eq!((first, second, third), ("xterm/nvim".into(), "xterm/nvim".into(), "xterm/bash".into()));
+}
+
+#[test]
+fn test_headless() {
+    use std::fs::{self, File};
+    use std::io::Write;
+    use std::os::unix::fs::{symlink, MetadataExt};
+
+    let root = std::env::temp_dir().join(format!("timmy-headless-{}", std::process::id()));
+    let process = |pid: u32, comm: &str, cwd: &str, tty: i32, cpu: u64| {
+        let dir = root.join(pid.to_string());
+        fs::create_dir_all(&dir).unwrap();
+        File::create(dir.join("stat"))
+            .unwrap()
+            .write_all(stat(pid, comm, 1, tty, pid as i32, cpu).as_bytes())
+            .unwrap();
+        if fs::symlink_metadata(dir.join("cwd")).is_err() {
+            symlink(cwd, dir.join("cwd")).unwrap();
+        }
+    };
+    process(10, "vim", "/home/me/timmy/src", 34816, 100);
+    process(11, "cargo", "/home/me/timmy", 0, 50);
+    process(12, "firefox", "/home/me", 0, 1000);
+    process(13, "bash", "/home/me", 34817, 5);
+    process(14, "timmy", "/home/me/timmy", 34818, 0);
+    let uid = fs::metadata(&root).unwrap().uid();
+    let start = at(0, 0);
+    let mut tracker = Headless::with_proc(procfs::Proc::at(&root), "/home/me/timmy".as_ref(), uid, 14, start);
+    process(10, "vim", "", 34816, 130);
+    process(11, "cargo", "", 0, 60);
+    process(12, "firefox", "", 0, 2000);
+    process(14, "timmy", "", 34818, 50);
+    tracker.poll(at(0, 40));
+    tracker.poll(at(1, 0));
+    let segments = tracker.finish(at(1, 10));
+    fs::remove_dir_all(&root).unwrap();
+    let programs: Vec<(&str, DateTime<Local>, DateTime<Local>)> =
+        segments.iter().map(|segment| (&segment.program[..], segment.start, segment.end)).collect();
+    assert_eq!(programs,
+               vec![("vim", at(0, 0), at(0, 30)),
+                    ("cargo", at(0, 30), at(0, 40)),
+                    ("bash", at(0, 40), at(0, 50)),
+                    ("vim", at(0, 50), at(1, 0)),
+                    ("bash", at(1, 0), at(1, 5)),
+                    ("vim", at(1, 5), at(1, 10))]);
+    assert!(segments.iter().all(|segment| segment.estimated));
+}
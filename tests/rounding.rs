@@ -0,0 +1,49 @@
+extern crate chrono;
+extern crate timmy;
+
+use chrono::*;
+use timmy::rounding::*;
+
+fn at(s: &str) -> DateTime<FixedOffset> {
+    DateTime::parse_from_rfc3339(&format!("2016-08-{}:00+01:00", s)).unwrap()
+}
+
+fn quarter_hours(per: Per, mode: Mode) -> Rounding {
+    Rounding { increment: Duration::minutes(15), per: per, mode: mode }
+}
+
+#[test]
+fn test_round() {
+    let up = quarter_hours(Per::Period, Mode::Up);
+    let nearest = quarter_hours(Per::Period, Mode::Nearest);
+    assert_eq!(up.round(Duration::minutes(1)), Duration::minutes(15));
+    assert_eq!(up.round(Duration::minutes(15)), Duration::minutes(15));
+    assert_eq!(up.round(Duration::minutes(16)), Duration::minutes(30));
+    assert_eq!(nearest.round(Duration::minutes(7)), Duration::zero());
+    assert_eq!(nearest.round(Duration::minutes(8)), Duration::minutes(15));
+    assert_eq!(nearest.round(Duration::minutes(22)), Duration::minutes(15));
+    assert_eq!(Rounding::none().round(Duration::minutes(7)), Duration::minutes(7));
+}
+
+#[test]
+fn test_total_per_period() {
+    let periods = vec![(at("23T09:00"), at("23T09:05")),
+                       (at("23T10:00"), at("23T10:05")),
+                       (at("24T09:00"), at("24T09:20"))];
+    let up = quarter_hours(Per::Period, Mode::Up);
+    assert_eq!(up.total(&periods), Duration::minutes(60));
+    assert_eq!(Rounding::none().total(&periods), Duration::minutes(30));
+}
+
+#[test]
+fn test_total_per_day() {
+    let periods = vec![(at("23T09:00"), at("23T09:05")),
+                       (at("23T10:00"), at("23T10:05")),
+                       (at("24T09:00"), at("24T09:20"))];
+    let up = quarter_hours(Per::Day, Mode::Up);
+    let days = up.day_totals(&periods);
+    assert_eq!(days.len(), 2);
+    assert_eq!(days[0], (at("23T09:00").date(), Duration::minutes(15)));
+    assert_eq!(days[1], (at("24T09:00").date(), Duration::minutes(30)));
+    assert_eq!(up.total(&periods), Duration::minutes(45));
+}
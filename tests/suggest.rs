@@ -0,0 +1,47 @@
+extern crate chrono;
+extern crate timmy;
+
+use std::path::Path;
+use chrono::*;
+use timmy::probe::Window;
+use timmy::suggest::*;
+
+fn rules() -> Vec<Rule> {
+    vec![Rule::new("timmy", "path", "/home/matt/code/timmy").unwrap(),
+         Rule::new("docs", "path", "/home/matt/code/timmy/docs").unwrap(),
+         Rule::new("client", "remote", "github.com/acme/").unwrap(),
+         Rule::new("client", "title", r"(?i)acme").unwrap(),
+         Rule::new("timmy", "title", r"timmy").unwrap()]
+}
+
+#[test]
+fn test_for_directory() {
+    let rules = rules();
+    let none: Vec<String> = vec![];
+    let acme = vec!["git@github.com:acme/site.git".to_string()];
+    let https = vec!["https://github.com/acme/site".to_string()];
+    assert_eq!(for_directory(&rules, Path::new("/home/matt/code/timmy/src"), &acme), Some("timmy"));
+    assert_eq!(for_directory(&rules, Path::new("/home/matt/code/timmy/docs/api"), &none), Some("docs"));
+    assert_eq!(for_directory(&rules, Path::new("/home/matt/code/timmy-old"), &none), None);
+    assert_eq!(for_directory(&rules, Path::new("/home/matt/code/site"), &acme), Some("client"));
+    assert_eq!(for_directory(&rules, Path::new("/home/matt/code/site"), &https), Some("client"));
+    assert!(Rule::new("broken", "title", "(").is_none());
+    assert!(Rule::new("broken", "host", "box").is_none());
+}
+
+#[test]
+fn test_drift() {
+    let at = |minute| Local.ymd(2016, 8, 23).and_hms(9, minute, 0);
+    let acme = Window::new("firefox", Some("ACME issues - Firefox"));
+    let timmy = Window::new("emacs", Some("main.rs - timmy"));
+    let mut drift = Drift::new(rules(), "timmy", Duration::minutes(10));
+    assert_eq!(drift.observe(Some(&acme), at(0)), None);
+    assert_eq!(drift.observe(Some(&timmy), at(5)), None);
+    assert_eq!(drift.observe(Some(&acme), at(6)), None);
+    assert_eq!(drift.observe(Some(&acme), at(15)), None);
+    assert_eq!(drift.observe(Some(&acme), at(16)), Some("client".into()));
+    assert_eq!(drift.observe(Some(&acme), at(30)), None);
+    assert_eq!(drift.observe(None, at(31)), None);
+    assert_eq!(drift.observe(Some(&acme), at(32)), None);
+    assert_eq!(drift.observe(Some(&acme), at(42)), Some("client".into()));
+}